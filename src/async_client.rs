@@ -0,0 +1,444 @@
+use std::io::{Cursor, Read};
+
+use tokio::io::{AsyncWriteExt, BufReader};
+
+use crate::async_stream::{read_body, read_head, AsyncStream};
+use crate::charset;
+use crate::client::{is_interim, is_persistent, Client};
+use crate::client_builder::ClientBuilder;
+use crate::cookie::CookieJar;
+use crate::encoding::decoder;
+use crate::error::{Error, Result};
+use crate::limits::LimitedReader;
+use crate::method::{IntoMethod, Method};
+use crate::request::Request;
+use crate::response::Response;
+use crate::uri::{IntoUri, Uri};
+
+// A client that does its I/O on tokio. It is built with the same
+// ClientBuilder as Client and follows the same settings, but reads each body
+// together with its response, so the connection is free for the next request
+// right away. The response cache, expect-continue and progress callbacks are
+// only supported by the blocking client; build_async fails when they are set.
+#[derive(Debug)]
+pub struct AsyncClient {
+    // Holds the settings and the request to send.
+    client: Client,
+    stream: Option<BufReader<AsyncStream>>,
+    response: Option<Response>,
+    body: Option<Vec<u8>>,
+    // A body given as a reader, read into memory so it can be sent again.
+    upload: Option<Vec<u8>>,
+}
+
+impl AsyncClient {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<U: IntoUri>(uri: U) -> ClientBuilder {
+        ClientBuilder::new().uri(uri)
+    }
+
+    pub(crate) fn from(mut client: Client) -> Result<AsyncClient> {
+        let upload = match client.take_upload() {
+            Some(upload) => {
+                let mut body = Vec::new();
+                upload.write_to(&mut body)?;
+                Some(body)
+            }
+            None => None,
+        };
+        Ok(AsyncClient {
+            client,
+            stream: None,
+            response: None,
+            body: None,
+            upload,
+        })
+    }
+
+    pub fn request(&self) -> Request {
+        self.client.request()
+    }
+
+    pub fn uri(&self) -> &Uri {
+        self.client.uri()
+    }
+
+    pub fn proxy(&self) -> Option<&Uri> {
+        self.client.proxy()
+    }
+
+    pub fn cookie_jar(&self) -> Option<&CookieJar> {
+        self.client.cookie_jar()
+    }
+
+    pub fn get<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::GET, uri)
+    }
+
+    pub fn post<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::POST, uri)
+    }
+
+    pub fn put<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::PUT, uri)
+    }
+
+    pub fn patch<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::PATCH, uri)
+    }
+
+    pub fn delete<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::DELETE, uri)
+    }
+
+    pub fn head<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::HEAD, uri)
+    }
+
+    // Starts a new request as Client::method does. The connection is kept
+    // when the uri has the same origin.
+    pub fn method<T: IntoMethod, U: IntoUri>(&mut self, method: T, uri: U) -> &mut Self {
+        let origin = self.client.uri().origin();
+        self.client.method(method, uri);
+        if self.client.uri().origin() != origin {
+            self.stream = None;
+        }
+        self.response = None;
+        self.body = None;
+        self.upload = None;
+        self
+    }
+
+    pub fn header<T: ToString + ?Sized, U: ToString + ?Sized>(
+        &mut self,
+        key: &T,
+        value: &U,
+    ) -> &mut Self {
+        self.client.header(key, value);
+        self
+    }
+
+    pub fn body(&mut self, body: &[u8]) -> &mut Self {
+        self.client.body(body);
+        self.upload = None;
+        self
+    }
+
+    // Opens a new connection to the current uri.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.stream = Some(self.connect().await?);
+        self.response = None;
+        self.body = None;
+        Ok(())
+    }
+
+    // Waits for the rate limiter without blocking the runtime.
+    async fn connect(&self) -> Result<BufReader<AsyncStream>> {
+        let (uri, proxy) = (self.client.uri(), self.client.proxy());
+        if let Some(limiter) = self.client.get_rate_limit() {
+            loop {
+                match limiter.try_acquire(uri, proxy) {
+                    Err(Error::RateLimited(wait)) if limiter.is_blocking() => {
+                        tokio::time::sleep(wait).await
+                    }
+                    result => break result?,
+                }
+            }
+        }
+        Ok(BufReader::new(AsyncStream::connect(uri, proxy).await?))
+    }
+
+    // Sends the request with the retries of the retry policy, as Client::send
+    // does.
+    pub async fn send(&mut self) -> Result<Response> {
+        if let Some(e) = self.client.take_error() {
+            return Err(e);
+        }
+        let mut retry = 0;
+        loop {
+            let result = if retry > 0 || self.stream.is_none() {
+                match self.reconnect().await {
+                    Ok(()) => self.fetch().await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                self.fetch().await
+            };
+            if result.is_err() {
+                self.stream = None;
+            }
+            retry += 1;
+            let policy = self.client.get_retry();
+            let method = self.client.get_request().get_method();
+            let delay = match policy.delay(&result, method, retry) {
+                Some(delay) => delay,
+                None => return result,
+            };
+            if let Some(proxy) = policy.next_proxy(self.client.proxy()) {
+                self.client.switch_proxy(proxy);
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn fetch(&mut self) -> Result<Response> {
+        let streamed = self.upload.is_some();
+        let mut redirects = Vec::new();
+        loop {
+            self.client.add_cookies();
+            let mut response = self.exchange().await?;
+            if self.client.authorize(&response) {
+                response = self.exchange().await?;
+            }
+            self.client.store_cookies(&response);
+            let origin = self.client.uri().origin();
+            if !self
+                .client
+                .follow_redirect(&response, &mut redirects, streamed)?
+            {
+                response.set_redirects(redirects);
+                self.response = Some(response.clone());
+                return Ok(response);
+            }
+            // An upload is only followed by a redirect that turns the request
+            // into a GET without a body.
+            self.upload = None;
+            if self.client.uri().origin() != origin {
+                self.stream = None;
+            }
+        }
+    }
+
+    async fn exchange(&mut self) -> Result<Response> {
+        let request = self.client.get_request();
+        request.validate()?;
+        if self.stream.is_none() {
+            self.stream = Some(self.connect().await?);
+        }
+        let stream = self.stream.as_mut().ok_or(Error::WrongHttp)?;
+        match &self.upload {
+            Some(upload) => {
+                stream.write_all(&request.msg_head()).await?;
+                stream.write_all(upload).await?;
+            }
+            None => stream.write_all(&request.msg()).await?,
+        }
+        stream.flush().await?;
+
+        let limits = *self.client.get_limits();
+        let response = loop {
+            let response = read_head(stream, &limits).await?;
+            if !is_interim(&response) {
+                break response;
+            }
+            if let Some(callback) = self.client.get_on_info() {
+                callback.call(&response);
+            }
+        };
+        let body = read_body(stream, &response, request.get_method(), limits.body_bytes).await?;
+        if !is_persistent(request, &response) {
+            self.stream = None;
+        }
+        self.body = Some(body);
+        Ok(response)
+    }
+
+    // The body of the last response, decoded as Content-Encoding says.
+    pub fn get_body(&mut self) -> Result<Vec<u8>> {
+        let response = self.response.as_ref().ok_or(Error::EmptyResponse)?;
+        let raw = self.body.take().ok_or(Error::EmptyResponse)?;
+        if !self.client.get_decompress() {
+            return Ok(raw);
+        }
+        let content_encoding = response.headers().get("Content-Encoding");
+        let mut reader = decoder(Box::new(Cursor::new(raw)), content_encoding.as_deref())?;
+        let mut body = Vec::new();
+        match self.client.get_limits().body_bytes {
+            Some(max) => LimitedReader::new(reader, max).read_to_end(&mut body)?,
+            None => reader.read_to_end(&mut body)?,
+        };
+        Ok(body)
+    }
+
+    pub fn text(&mut self) -> Result<String> {
+        let body = self.get_body()?;
+        let content_type = self
+            .response
+            .as_ref()
+            .and_then(|response| response.headers().get("Content-Type"));
+        Ok(charset::decode_text(
+            &body,
+            content_type.as_deref(),
+            self.client.get_sniff_charset(),
+        ))
+    }
+
+    pub fn text_with_charset(&mut self, charset: &str) -> Result<String> {
+        let body = self.get_body()?;
+        charset::decode(&body, charset)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T> {
+        let body = self.get_body()?;
+        let status = match &self.response {
+            Some(response) => response.status_code(),
+            None => return Err(Error::EmptyResponse),
+        };
+        serde_json::from_slice(&body).map_err(|e| Error::JsonDecode(status, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::limits::Limits;
+    use crate::redirect::RedirectPolicy;
+
+    #[tokio::test]
+    async fn async_client_send() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=iso-8859-1\r\n\
+              Content-Length: 4\r\n\r\ncaf\xE9"
+                .to_vec(),
+            b"HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n\
+              3\r\ntwo\r\n0\r\n\r\n"
+                .to_vec(),
+        ]);
+        let mut client = AsyncClient::new(uri.as_str())
+            .header("X-Shared", "yes")
+            .redirect(RedirectPolicy::limited(5))
+            .build_async()
+            .await
+            .unwrap();
+        let response = client.send().await.unwrap();
+        assert_eq!(response.redirects().len(), 1);
+        assert_eq!(client.text().unwrap(), "café");
+
+        let response = client
+            .post(uri.as_str())
+            .body(b"data")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(u16::from(response.status_code()), 201);
+        assert_eq!(client.text().unwrap(), "two");
+
+        rx.recv().unwrap();
+        let second = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(second.starts_with("GET "));
+        assert!(second.contains("/next HTTP/1.1\r\n"));
+        let third = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(third.starts_with("POST "));
+        assert!(third.contains("X-Shared: yes\r\n"));
+        assert!(third.ends_with("\r\n\r\ndata"));
+    }
+
+    #[tokio::test]
+    async fn async_client_reuse_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            for body in &["one", "two"] {
+                crate::limits::read_head(&mut stream, &Limits::default()).unwrap();
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{}", body);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let mut client = AsyncClient::new(format!("{}/a", uri))
+            .header("Connection", "keep-alive")
+            .build_async()
+            .await
+            .unwrap();
+        client.send().await.unwrap();
+        assert_eq!(client.text().unwrap(), "one");
+        client.get(format!("{}/b", uri)).send().await.unwrap();
+        assert_eq!(client.text().unwrap(), "two");
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn async_client_upload() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let mut client = AsyncClient::new(uri.as_str())
+            .method("PUT")
+            .body_reader(&b"streamed"[..])
+            .build_async()
+            .await
+            .unwrap();
+        client.send().await.unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(request.ends_with("8\r\nstreamed\r\n0\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn async_client_unsupported() {
+        let result = AsyncClient::new("http://127.0.0.1:1/")
+            .expect_continue(Duration::from_secs(1))
+            .build_async()
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedAsync("expect_continue"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn async_client_upload_see_other() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            crate::limits::read_head(&mut stream, &Limits::default()).unwrap();
+            let mut body = Vec::new();
+            let mut byte = [0u8; 1];
+            while !body.ends_with(b"0\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                body.push(byte[0]);
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 303 See Other\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n",
+                )
+                .unwrap();
+            // Anything sent after the head of the GET is a leaked body.
+            let (mut stream, _) = listener.accept().unwrap();
+            let head = crate::limits::read_head(&mut stream, &Limits::default()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_millis(100)))
+                .unwrap();
+            let mut extra = Vec::new();
+            let _ = stream.read_to_end(&mut extra);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (body, String::from_utf8(head).unwrap(), extra)
+        });
+        let mut client = AsyncClient::new(uri.as_str())
+            .method("POST")
+            .body_reader(&b"streamed"[..])
+            .redirect(RedirectPolicy::limited(5))
+            .build_async()
+            .await
+            .unwrap();
+        let response = client.send().await.unwrap();
+        assert_eq!(response.redirects().len(), 1);
+        let (body, head, extra) = server.join().unwrap();
+        assert_eq!(body, b"8\r\nstreamed\r\n0\r\n\r\n");
+        assert!(head.starts_with("GET "));
+        assert!(head.contains("/done HTTP/1.1\r\n"));
+        assert!(extra.is_empty());
+    }
+}
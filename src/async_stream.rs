@@ -0,0 +1,295 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsConnector, TlsStream};
+
+use crate::body::{chunk_size, Framing, MAX_CHUNK_LINE};
+use crate::error::{Error, Result};
+use crate::limits::{HeadBuf, Limits};
+use crate::method::Method;
+use crate::response::Response;
+use crate::socks::{
+    auth_request, auth_response, choise_communicated, get_server_reponse, host_len,
+    initial_greeting, request_connection, SocksAuth,
+};
+use crate::uri::Uri;
+
+#[derive(Debug)]
+pub enum AsyncStream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncStream {
+    // Connects to the target directly or through the proxy, the same way
+    // Transport does for the blocking client.
+    pub async fn connect(uri: &Uri, proxy: Option<&Uri>) -> Result<AsyncStream> {
+        let proxy = match proxy {
+            Some(proxy) => proxy,
+            None => return AsyncStream::direct(uri).await,
+        };
+        match proxy.scheme() {
+            "http" | "https" => Ok(AsyncStream::Tcp(
+                TcpStream::connect(proxy.host_port()).await?,
+            )),
+            "socks5" | "socks5h" => AsyncStream::socks5(proxy, uri).await,
+            s => Err(Error::UnsupportedScheme(s.to_owned())),
+        }
+    }
+
+    pub async fn direct(uri: &Uri) -> Result<AsyncStream> {
+        let stream = TcpStream::connect(uri.host_port()).await?;
+        AsyncStream::secure(uri, stream).await
+    }
+
+    pub async fn socks5(proxy: &Uri, target: &Uri) -> Result<AsyncStream> {
+        let auth = SocksAuth::from_uri(proxy);
+        let mut stream = TcpStream::connect(proxy.host_port()).await?;
+        stream.write_all(&initial_greeting(&auth)).await?;
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).await?;
+        if choise_communicated(buf, &auth)? {
+            stream.write_all(&auth_request(&auth)).await?;
+            stream.read_exact(&mut buf).await?;
+            auth_response(buf)?;
+        }
+        stream.write_all(&request_connection(target)).await?;
+        let mut buf = [0u8; 3];
+        stream.read_exact(&mut buf).await?;
+        get_server_reponse(buf)?;
+        // The bound address and port that end the reply aren't used.
+        let address_type = stream.read_u8().await?;
+        let len = match host_len(address_type)? {
+            Some(len) => len,
+            None => stream.read_u8().await? as usize,
+        };
+        let mut bound = vec![0u8; len + 2];
+        stream.read_exact(&mut bound).await?;
+        AsyncStream::secure(target, stream).await
+    }
+
+    async fn secure(target: &Uri, stream: TcpStream) -> Result<AsyncStream> {
+        if !target.is_ssl() {
+            return Ok(AsyncStream::Tcp(stream));
+        }
+        let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+        let stream = connector.connect(target.host(), stream).await?;
+        Ok(AsyncStream::Tls(Box::new(stream)))
+    }
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            AsyncStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            AsyncStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            AsyncStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            AsyncStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+pub async fn read_head<R: AsyncRead + Unpin>(reader: &mut R, limits: &Limits) -> Result<Response> {
+    let mut head = HeadBuf::new(limits);
+    let mut byte = [0u8; 1];
+    while reader.read(&mut byte).await? == 1 {
+        if head.push(byte[0])? {
+            break;
+        }
+    }
+    Response::from_head(&head.finish()?)
+}
+
+// Reads the whole body of a response, framed the same way as Body does it.
+pub async fn read_body<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    response: &Response,
+    method: &Method,
+    limit: Option<u64>,
+) -> Result<Vec<u8>> {
+    let too_large = |len: u64| match limit {
+        Some(max) if len > max => Err(Error::BodyTooLarge(max)),
+        _ => Ok(()),
+    };
+    let mut body = Vec::new();
+    match Framing::new(response, method)? {
+        Framing::Empty | Framing::Done => (),
+        Framing::Length(len) => {
+            too_large(len)?;
+            (&mut *reader).take(len).read_to_end(&mut body).await?;
+            if (body.len() as u64) < len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        Framing::Chunked(_) => loop {
+            let size = chunk_size(&read_line(reader).await?)?;
+            if size == 0 {
+                while !read_line(reader).await?.is_empty() {}
+                break;
+            }
+            let end = (body.len() as u64)
+                .checked_add(size)
+                .ok_or(Error::ParseChunk)?;
+            too_large(end)?;
+            // Grows with the data that arrives instead of the announced size.
+            (&mut *reader).take(size).read_to_end(&mut body).await?;
+            if (body.len() as u64) < end {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if !read_line(reader).await?.is_empty() {
+                return Err(Error::ParseChunk);
+            }
+        },
+        Framing::Close => {
+            let max = limit.map_or(u64::MAX, |max| max.saturating_add(1));
+            (&mut *reader).take(max).read_to_end(&mut body).await?;
+            too_large(body.len() as u64)?;
+        }
+    }
+    Ok(body)
+}
+
+// Reads a line of a chunked body without its line ending.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_CHUNK_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if !line.ends_with(b"\n") {
+        if line.len() > MAX_CHUNK_LINE {
+            return Err(Error::ParseChunk);
+        }
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tokio::io::BufReader;
+
+    // Answers one request on a connection the proxy has set up.
+    fn respond(stream: &mut std::net::TcpStream) -> Vec<u8> {
+        let head = crate::limits::read_head(stream, &Limits::default()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n")
+            .unwrap();
+        head
+    }
+
+    #[tokio::test]
+    async fn async_stream_socks5() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("socks5://user:pass@{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[5, 2]).unwrap();
+            let mut auth = [0u8; 11];
+            stream.read_exact(&mut auth).unwrap();
+            stream.write_all(&[1, 0]).unwrap();
+            let mut request = [0u8; 5];
+            stream.read_exact(&mut request).unwrap();
+            let mut host = vec![0u8; request[4] as usize + 2];
+            stream.read_exact(&mut host).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 3, 4, b'h', b'o', b's', b't', 0, 80])
+                .unwrap();
+            let head = respond(&mut stream);
+            (greeting, auth, host, head)
+        });
+
+        let target: Uri = "http://example.org/".parse().unwrap();
+        let stream = AsyncStream::socks5(&proxy.parse().unwrap(), &target)
+            .await
+            .unwrap();
+        let mut stream = BufReader::new(stream);
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.org\r\n\r\n")
+            .await
+            .unwrap();
+        let response = read_head(&mut stream, &Limits::default()).await.unwrap();
+        let body = read_body(&mut stream, &response, &Method::GET, None)
+            .await
+            .unwrap();
+        assert_eq!(body, b"ok");
+
+        let (greeting, auth, host, head) = server.join().unwrap();
+        assert_eq!(greeting, [5, 1, 2]);
+        assert_eq!(&auth, b"\x01\x04user\x04pass");
+        assert_eq!(&host, b"example.org\x00\x50");
+        assert!(head.starts_with(b"GET / HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn async_stream_body_limit() {
+        let response =
+            Response::from_head(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n").unwrap();
+        let mut body = &b"hello"[..];
+        assert!(matches!(
+            read_body(&mut body, &response, &Method::GET, Some(4)).await,
+            Err(Error::BodyTooLarge(4))
+        ));
+        let response = Response::from_head(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+        let mut body = &b"hello"[..];
+        assert!(matches!(
+            read_body(&mut body, &response, &Method::GET, Some(4)).await,
+            Err(Error::BodyTooLarge(4))
+        ));
+        let response =
+            Response::from_head(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").unwrap();
+        let mut body = &b"ffffffffffffffff\r\nhello"[..];
+        assert!(matches!(
+            read_body(&mut body, &response, &Method::GET, Some(4)).await,
+            Err(Error::BodyTooLarge(4))
+        ));
+        let mut body = &b"2\r\nok\r\nffffffffffffffff\r\nhello"[..];
+        assert!(read_body(&mut body, &response, &Method::GET, None)
+            .await
+            .is_err());
+    }
+}
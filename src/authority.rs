@@ -38,10 +38,7 @@ impl Authority {
     }
 
     pub fn port(&self) -> Option<u16> {
-        match &self.port {
-            Some(p) => Some(self.inner[*p].parse().unwrap()),
-            None => None,
-        }
+        self.port.map(|p| self.inner[p].parse().unwrap())
     }
 
     pub fn base64_auth(&self) -> Option<String> {
@@ -62,8 +59,8 @@ impl FromStr for Authority {
         let mut password = None;
 
         let uri_part = if s.contains('@') {
-            let (info, part) = get_chunks(&s, Some(RangeUsize::new(0, s.len())), "@", true, false);
-            let (name, pass) = get_chunks(&s, info, ":", true, false);
+            let (info, part) = get_chunks(s, Some(RangeUsize::new(0, s.len())), "@", true, false);
+            let (name, pass) = get_chunks(s, info, ":", true, false);
 
             username = name;
             password = pass;
@@ -78,7 +75,7 @@ impl FromStr for Authority {
         } else {
             ":"
         };
-        let (host, port) = get_chunks(&s, uri_part, split_by, true, false);
+        let (host, port) = get_chunks(s, uri_part, split_by, true, false);
         let host = host.ok_or(Error::ParseHost)?;
 
         if let Some(p) = port {
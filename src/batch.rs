@@ -0,0 +1,389 @@
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::client::Client;
+use crate::client_builder::ClientBuilder;
+use crate::error::{Error, Result};
+use crate::response::Response;
+
+// How often a waiting worker checks whether the batch was cancelled.
+const CANCEL_CHECK: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub struct Outcome<T> {
+    // Position of the builder in the input.
+    pub index: usize,
+    pub result: Result<T>,
+}
+
+// Stops a running batch: no new request is started, the ones in flight are
+// finished and yielded.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+// Runs many requests on a bounded pool of threads. The number of threads is
+// the global limit on requests in flight; per_host adds a limit for each
+// target host and port.
+#[derive(Debug)]
+pub struct Batch {
+    threads: usize,
+    per_host: Option<usize>,
+    cancel: CancelHandle,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Batch::new()
+    }
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch {
+            threads: 8,
+            per_host: None,
+            cancel: CancelHandle::default(),
+        }
+    }
+
+    pub fn threads(mut self, threads: usize) -> Batch {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn per_host(mut self, limit: usize) -> Batch {
+        self.per_host = Some(limit.max(1));
+        self
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    // Builds and sends every client, yielding the response with the client it
+    // came from, so the body can still be read.
+    pub fn run<I>(self, builders: I) -> Results<(Response, Client)>
+    where
+        I: IntoIterator<Item = ClientBuilder>,
+        I::IntoIter: Send + 'static,
+    {
+        self.run_with(builders, |mut client| {
+            let response = client.send()?;
+            Ok((response, client))
+        })
+    }
+
+    // Builds every client and hands it to `job`, yielding what it returns.
+    pub fn run_with<I, F, T>(self, builders: I, job: F) -> Results<T>
+    where
+        I: IntoIterator<Item = ClientBuilder>,
+        I::IntoIter: Send + 'static,
+        F: Fn(Client) -> Result<T> + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let queue = Arc::new((
+            Mutex::new(Queue {
+                builders: Box::new(builders.into_iter().enumerate()),
+                exhausted: false,
+                deferred: VecDeque::new(),
+                max_deferred: self.threads * 4,
+                per_host: self.per_host,
+                running: HashMap::new(),
+            }),
+            Condvar::new(),
+        ));
+        let job = Arc::new(job);
+        let (tx, rx) = channel();
+        for _ in 0..self.threads {
+            let worker = Worker {
+                queue: queue.clone(),
+                job: job.clone(),
+                tx: tx.clone(),
+                cancel: self.cancel.clone(),
+            };
+            thread::spawn(move || worker.run());
+        }
+        Results {
+            rx,
+            cancel: self.cancel,
+        }
+    }
+}
+
+// Yields outcomes in the order the requests complete. Dropping it cancels
+// the batch.
+#[derive(Debug)]
+pub struct Results<T> {
+    rx: Receiver<Outcome<T>>,
+    cancel: CancelHandle,
+}
+
+impl<T> Results<T> {
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl<T> Iterator for Results<T> {
+    type Item = Outcome<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> Drop for Results<T> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+type Item = (usize, ClientBuilder);
+
+struct Queue {
+    builders: Box<dyn Iterator<Item = Item> + Send>,
+    exhausted: bool,
+    // Builders whose host was at its limit when they were reached.
+    deferred: VecDeque<(String, Item)>,
+    max_deferred: usize,
+    per_host: Option<usize>,
+    running: HashMap<String, usize>,
+}
+
+impl Queue {
+    fn is_free(&self, host: &str) -> bool {
+        match self.per_host {
+            Some(limit) => self.running.get(host).copied().unwrap_or(0) < limit,
+            None => true,
+        }
+    }
+
+    // Takes the next builder whose host has a free slot, deferred ones first.
+    fn next(&mut self) -> Option<(String, Item)> {
+        if let Some(pos) = self
+            .deferred
+            .iter()
+            .position(|(host, _)| self.is_free(host))
+        {
+            return self.deferred.remove(pos);
+        }
+        while !self.exhausted && self.deferred.len() < self.max_deferred {
+            let item = match self.builders.next() {
+                Some(item) => item,
+                None => {
+                    self.exhausted = true;
+                    break;
+                }
+            };
+            let host = item
+                .1
+                .get_uri()
+                .map(|uri| uri.host_port())
+                .unwrap_or_default();
+            if self.is_free(&host) {
+                return Some((host, item));
+            }
+            self.deferred.push_back((host, item));
+        }
+        None
+    }
+
+    fn is_done(&self) -> bool {
+        self.exhausted && self.deferred.is_empty()
+    }
+}
+
+struct Worker<F, T> {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    job: Arc<F>,
+    tx: Sender<Outcome<T>>,
+    cancel: CancelHandle,
+}
+
+impl<F, T> Worker<F, T>
+where
+    F: Fn(Client) -> Result<T>,
+{
+    fn run(self) {
+        while let Some((host, (index, builder))) = self.take() {
+            let slot = Slot {
+                queue: &self.queue,
+                host,
+            };
+            // A panic is yielded as an error so the worker and the slot of
+            // the host aren't lost.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                builder.build().and_then(|client| (self.job)(client))
+            }))
+            .unwrap_or(Err(Error::JobPanicked));
+            drop(slot);
+            if self.tx.send(Outcome { index, result }).is_err() {
+                self.cancel.cancel();
+            }
+        }
+    }
+
+    // Waits for a builder that may start, or returns None when there are no
+    // more or the batch was cancelled.
+    fn take(&self) -> Option<(String, Item)> {
+        let (queue, ready) = &*self.queue;
+        let mut queue = queue.lock().ok()?;
+        loop {
+            if self.cancel.is_cancelled() || queue.is_done() {
+                return None;
+            }
+            if let Some((host, item)) = queue.next() {
+                *queue.running.entry(host.clone()).or_insert(0) += 1;
+                return Some((host, item));
+            }
+            queue = ready.wait_timeout(queue, CANCEL_CHECK).ok()?.0;
+        }
+    }
+}
+
+// A request in flight for a host, given back when dropped.
+struct Slot<'a> {
+    queue: &'a (Mutex<Queue>, Condvar),
+    host: String,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let (queue, ready) = self.queue;
+        if let Ok(mut queue) = queue.lock() {
+            if let Some(running) = queue.running.get_mut(&self.host) {
+                *running -= 1;
+                if *running == 0 {
+                    queue.running.remove(&self.host);
+                }
+            }
+        }
+        ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn batch_run() {
+        let responses = (0..5)
+            .map(|i| format!("HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n{}", i).into_bytes())
+            .collect();
+        let (uri, _rx) = crate::tests::serve(responses);
+        let builders: Vec<ClientBuilder> = (0..6)
+            .map(|i| match i {
+                5 => Client::new("http://"),
+                _ => Client::new(uri.as_str()),
+            })
+            .collect();
+        let mut outcomes: Vec<_> = Batch::new().threads(3).run(builders).collect();
+        outcomes.sort_by_key(|outcome| outcome.index);
+        assert_eq!(outcomes.len(), 6);
+        let mut bodies: Vec<String> = outcomes[..5]
+            .iter_mut()
+            .map(|outcome| match &mut outcome.result {
+                Ok((response, client)) => {
+                    assert!(response.status_code().is_success());
+                    client.text().unwrap()
+                }
+                Err(e) => panic!("{}", e),
+            })
+            .collect();
+        bodies.sort();
+        assert_eq!(bodies, vec!["0", "1", "2", "3", "4"]);
+        assert!(outcomes[5].result.is_err());
+    }
+
+    #[test]
+    fn batch_per_host() {
+        // Connections wait in the backlog, which is enough for build.
+        let listeners: Vec<TcpListener> = (0..2)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let uris: Vec<String> = listeners
+            .iter()
+            .map(|l| format!("http://{}", l.local_addr().unwrap()))
+            .collect();
+        let builders: Vec<ClientBuilder> =
+            (0..6).map(|i| Client::new(uris[i % 2].as_str())).collect();
+        let running = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (counts, max) = (running.clone(), most.clone());
+        let outcomes = Batch::new()
+            .threads(4)
+            .per_host(1)
+            .run_with(builders, move |client| {
+                let host = client.uri().host_port();
+                {
+                    let mut counts = counts.lock().unwrap();
+                    let count = counts.entry(host.clone()).or_insert(0);
+                    *count += 1;
+                    max.fetch_max(*count, Ordering::SeqCst);
+                }
+                thread::sleep(Duration::from_millis(20));
+                *counts.lock().unwrap().get_mut(&host).unwrap() -= 1;
+                Ok(())
+            });
+        assert_eq!(outcomes.filter(|outcome| outcome.result.is_ok()).count(), 6);
+        assert_eq!(most.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn batch_panic() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let builders: Vec<ClientBuilder> = (0..3).map(|_| Client::new(uri.as_str())).collect();
+        let calls = AtomicUsize::new(0);
+        let outcomes: Vec<_> = Batch::new()
+            .threads(2)
+            .per_host(1)
+            .run_with(builders, move |_| {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("job failed");
+                }
+                Ok(())
+            })
+            .collect();
+        assert_eq!(outcomes.len(), 3);
+        let panicked = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome.result, Err(Error::JobPanicked)))
+            .count();
+        assert_eq!(panicked, 1);
+    }
+
+    #[test]
+    fn batch_cancel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let builders: Vec<ClientBuilder> = (0..20).map(|_| Client::new(uri.as_str())).collect();
+        let batch = Batch::new().threads(1);
+        let cancel = batch.cancel_handle();
+        let mut outcomes = batch.run_with(builders, |_| {
+            thread::sleep(Duration::from_millis(10));
+            Ok(())
+        });
+        assert!(outcomes.next().is_some());
+        cancel.cancel();
+        assert!(outcomes.count() <= 1);
+    }
+}
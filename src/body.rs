@@ -0,0 +1,254 @@
+use std::io::{self, Read};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::method::Method;
+use crate::response::Response;
+use crate::transport::Transport;
+
+pub(crate) const MAX_CHUNK_LINE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Framing {
+    Empty,
+    Length(u64),
+    Chunked(u64),
+    Close,
+    Done,
+}
+
+impl Framing {
+    pub(crate) fn new(response: &Response, method: &Method) -> Result<Framing> {
+        let code = u16::from(response.status_code());
+        if *method == Method::HEAD || response.status_code().is_info() || code == 204 || code == 304
+        {
+            return Ok(Framing::Empty);
+        }
+
+        if let Some(encoding) = response.headers().get("Transfer-Encoding") {
+            if encoding
+                .split(',')
+                .any(|e| e.trim().eq_ignore_ascii_case("chunked"))
+            {
+                return Ok(Framing::Chunked(0));
+            }
+        }
+
+        match response.headers().get("Content-Length") {
+            Some(len) => Ok(Framing::Length(len.trim().parse()?)),
+            None => Ok(Framing::Close),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Body {
+    transport: Transport,
+    framing: Framing,
+    idle: Option<Arc<Mutex<Option<Transport>>>>,
+}
+
+impl Body {
+    pub fn new(transport: Transport, response: &Response, method: &Method) -> Result<Body> {
+        let framing = Framing::new(response, method)?;
+        let framing = if framing == Framing::Empty || framing == Framing::Length(0) {
+            Framing::Done
+        } else {
+            framing
+        };
+        Ok(Body {
+            transport,
+            framing,
+            idle: None,
+        })
+    }
+
+    pub fn content_len(&self) -> Option<u64> {
+        match self.framing {
+            Framing::Length(len) => Some(len),
+            _ => None,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.framing == Framing::Done
+    }
+
+    pub fn into_transport(self) -> Transport {
+        self.transport
+    }
+
+    // Hands the connection over to `idle` once the body has been read to its
+    // end, so that the next request can reuse it. A body that ends with the
+    // connection is never handed over.
+    pub fn release_to(mut self, idle: Arc<Mutex<Option<Transport>>>) -> Body {
+        if self.framing != Framing::Close {
+            self.idle = Some(idle);
+            if self.is_done() {
+                self.release();
+            }
+        }
+        self
+    }
+
+    fn release(&mut self) {
+        if let Some(idle) = self.idle.take() {
+            if let Ok(mut idle) = idle.lock() {
+                *idle = Some(mem::take(&mut self.transport));
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        let stream = self.transport.stream_mut().map_err(to_io)?;
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+            if line.len() > MAX_CHUNK_LINE {
+                return Err(to_io(Error::ParseChunk));
+            }
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(line)
+    }
+
+    fn next_chunk(&mut self) -> io::Result<u64> {
+        let size = chunk_size(&self.read_line()?).map_err(to_io)?;
+        if size == 0 {
+            while !self.read_line()?.is_empty() {}
+        }
+        Ok(size)
+    }
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.framing {
+            Framing::Empty | Framing::Done => Ok(0),
+            Framing::Length(remaining) => {
+                let max = remaining.min(buf.len() as u64) as usize;
+                let stream = self.transport.stream_mut().map_err(to_io)?;
+                let read = stream.read(&mut buf[..max])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                self.framing = match remaining - read as u64 {
+                    0 => Framing::Done,
+                    left => Framing::Length(left),
+                };
+                if self.is_done() {
+                    self.release();
+                }
+                Ok(read)
+            }
+            Framing::Chunked(0) => {
+                let size = self.next_chunk()?;
+                if size == 0 {
+                    self.framing = Framing::Done;
+                    self.release();
+                    return Ok(0);
+                }
+                self.framing = Framing::Chunked(size);
+                self.read(buf)
+            }
+            Framing::Chunked(remaining) => {
+                let max = remaining.min(buf.len() as u64) as usize;
+                let stream = self.transport.stream_mut().map_err(to_io)?;
+                let read = stream.read(&mut buf[..max])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let left = remaining - read as u64;
+                if left == 0 && !self.read_line()?.is_empty() {
+                    return Err(to_io(Error::ParseChunk));
+                }
+                self.framing = Framing::Chunked(left);
+                Ok(read)
+            }
+            Framing::Close => {
+                let stream = self.transport.stream_mut().map_err(to_io)?;
+                let read = stream.read(buf)?;
+                if read == 0 {
+                    self.framing = Framing::Done;
+                }
+                Ok(read)
+            }
+        }
+    }
+}
+
+// Parses a chunk-size line without its line ending, ignoring extensions.
+pub(crate) fn chunk_size(line: &[u8]) -> Result<u64> {
+    let line = std::str::from_utf8(line).map_err(|_| Error::ParseChunk)?;
+    let size = line.split(';').next().unwrap_or("").trim();
+    u64::from_str_radix(size, 16).map_err(|_| Error::ParseChunk)
+}
+
+fn to_io(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpStream;
+    use crate::uri::Uri;
+
+    fn body(response: &[u8]) -> (Response, Body) {
+        let (uri, _) = crate::tests::serve(vec![response.to_vec()]);
+        let mut stream = HttpStream::connect(&uri.parse::<Uri>().unwrap()).unwrap();
+        stream.send_request(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let response = stream.get_response().unwrap();
+        let body = Body::new(Transport::Stream(stream), &response, &Method::GET).unwrap();
+        (response, body)
+    }
+
+    #[test]
+    fn body_length() {
+        let (_, mut body) = body(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello world");
+        let mut buf = String::new();
+        body.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello");
+        assert!(body.is_done());
+    }
+
+    #[test]
+    fn body_chunked() {
+        let (_, mut body) = body(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n",
+        );
+        let mut buf = String::new();
+        body.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello world");
+    }
+
+    #[test]
+    fn body_close() {
+        let (_, mut body) = body(b"HTTP/1.1 200 OK\r\n\r\nhello world");
+        let mut buf = String::new();
+        body.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello world");
+    }
+
+    #[test]
+    fn body_no_content() {
+        let (_, mut body) = body(b"HTTP/1.1 204 No Content\r\n\r\n");
+        let mut buf = Vec::new();
+        body.read_to_end(&mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+}
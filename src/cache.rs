@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::date::parse_http_date;
+use crate::error::{Error, Result};
+use crate::hash::{sha256, to_hex};
+use crate::headers::Headers;
+use crate::response::Response;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    uri: String,
+    vary: Vec<(String, Option<String>)>,
+    response: Response,
+    body: Vec<u8>,
+    response_time: SystemTime,
+}
+
+impl Entry {
+    pub fn new(
+        uri: &str,
+        request_headers: &Headers,
+        response: Response,
+        body: Vec<u8>,
+        response_time: SystemTime,
+    ) -> Entry {
+        let vary = response
+            .headers()
+            .get_all("Vary")
+            .iter()
+            .flat_map(|v| v.split(','))
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let value = request_headers.get(&name);
+                (name, value)
+            })
+            .collect();
+        Entry {
+            uri: uri.to_string(),
+            vary,
+            response,
+            body,
+            response_time,
+        }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn matches(&self, uri: &str, request_headers: &Headers) -> bool {
+        self.uri == uri
+            && self
+                .vary
+                .iter()
+                .all(|(name, value)| request_headers.get(name) == *value)
+    }
+
+    pub fn etag(&self) -> Option<String> {
+        self.response.headers().get("ETag")
+    }
+
+    pub fn last_modified(&self) -> Option<String> {
+        self.response.headers().get("Last-Modified")
+    }
+
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        let directives = directives(self.response.headers());
+        if has(&directives, "no-cache") {
+            return false;
+        }
+        self.freshness_lifetime(&directives) > self.age(now)
+    }
+
+    // RFC 7234, section 4.2.1.
+    fn freshness_lifetime(&self, directives: &[(String, Option<String>)]) -> Duration {
+        if let Some(max_age) = max_age(directives) {
+            return Duration::from_secs(max_age);
+        }
+        let headers = self.response.headers();
+        let date = self.date();
+        if let Some(expires) = headers.get("Expires") {
+            return match parse_http_date(&expires) {
+                Some(expires) => expires.duration_since(date).unwrap_or_default(),
+                None => Duration::from_secs(0),
+            };
+        }
+        match headers
+            .get("Last-Modified")
+            .and_then(|l| parse_http_date(&l))
+        {
+            Some(modified) => date.duration_since(modified).unwrap_or_default() / 10,
+            None => Duration::from_secs(0),
+        }
+    }
+
+    // RFC 7234, section 4.2.3.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        let apparent_age = self
+            .response_time
+            .duration_since(self.date())
+            .unwrap_or_default();
+        let age_value = self
+            .response
+            .headers()
+            .get("Age")
+            .and_then(|age| age.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let resident_time = now.duration_since(self.response_time).unwrap_or_default();
+        apparent_age.max(age_value) + resident_time
+    }
+
+    fn date(&self) -> SystemTime {
+        self.response
+            .headers()
+            .get("Date")
+            .and_then(|date| parse_http_date(&date))
+            .unwrap_or(self.response_time)
+    }
+
+    // Applies the headers of a 304 response, RFC 7234, section 4.3.4.
+    pub fn update(&mut self, not_modified: &Response, response_time: SystemTime) {
+        let headers = self.response.headers_mut();
+        for (key, value) in not_modified.headers().iter() {
+            if !key.eq_ignore_ascii_case("Content-Length")
+                && !key.eq_ignore_ascii_case("Transfer-Encoding")
+            {
+                headers.insert(key, value);
+            }
+        }
+        self.response_time = response_time;
+    }
+
+    fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        let time = self
+            .response_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        writeln!(w, "{}\n{}\n{}", self.uri, time, self.vary.len())?;
+        for (name, value) in &self.vary {
+            match value {
+                Some(value) => writeln!(w, "{}\n+{}", name, value)?,
+                None => writeln!(w, "{}\n-", name)?,
+            }
+        }
+        let head = self.response.to_head();
+        writeln!(w, "{} {}", head.len(), self.body.len())?;
+        w.write_all(&head)?;
+        w.write_all(&self.body)?;
+        Ok(())
+    }
+
+    fn read_from<R: BufRead>(r: &mut R) -> Result<Option<Entry>> {
+        let uri = match read_line(r)? {
+            Some(uri) => uri,
+            None => return Ok(None),
+        };
+        let time: u64 = read_line(r)?.ok_or(Error::ParseCache)?.parse()?;
+        let count: usize = read_line(r)?.ok_or(Error::ParseCache)?.parse()?;
+        let mut vary = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name = read_line(r)?.ok_or(Error::ParseCache)?;
+            let value = read_line(r)?.ok_or(Error::ParseCache)?;
+            let value = value.strip_prefix('+').map(|v| v.to_string());
+            vary.push((name, value));
+        }
+        let lens = read_line(r)?.ok_or(Error::ParseCache)?;
+        let (head_len, body_len) = lens.split_once(' ').ok_or(Error::ParseCache)?;
+        let mut head = vec![0u8; head_len.parse()?];
+        r.read_exact(&mut head)?;
+        let mut body = vec![0u8; body_len.parse()?];
+        r.read_exact(&mut body)?;
+        Ok(Some(Entry {
+            uri,
+            vary,
+            response: Response::from_head(&head)?,
+            body,
+            response_time: UNIX_EPOCH + Duration::from_secs(time),
+        }))
+    }
+}
+
+fn read_line<R: BufRead>(r: &mut R) -> Result<Option<String>> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+#[derive(Debug)]
+enum Storage {
+    Memory(HashMap<String, Vec<Entry>>),
+    Disk(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct Cache(Arc<Mutex<Storage>>);
+
+impl Cache {
+    pub fn memory() -> Cache {
+        Cache(Arc::new(Mutex::new(Storage::Memory(HashMap::new()))))
+    }
+
+    pub fn disk<P: AsRef<Path>>(dir: P) -> Result<Cache> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Cache(Arc::new(Mutex::new(Storage::Disk(
+            dir.as_ref().to_path_buf(),
+        )))))
+    }
+
+    pub fn get(&self, uri: &str, request_headers: &Headers) -> Option<Entry> {
+        self.entries(uri)
+            .ok()?
+            .into_iter()
+            .find(|entry| entry.matches(uri, request_headers))
+    }
+
+    // Replaces the stored variant with the same Vary values, if any.
+    pub fn insert(&self, entry: Entry) -> Result<()> {
+        let mut storage = self.0.lock().unwrap();
+        match &mut *storage {
+            Storage::Memory(map) => {
+                let entries = map.entry(entry.uri.clone()).or_default();
+                entries.retain(|e| e.vary != entry.vary);
+                entries.push(entry);
+                Ok(())
+            }
+            Storage::Disk(dir) => {
+                let path = file_name(dir, &entry.uri);
+                let mut entries = read_file(&path)?;
+                // A file may hold other uris whose names collide.
+                entries.retain(|e| e.uri != entry.uri || e.vary != entry.vary);
+                entries.push(entry);
+                write_file(&path, &entries)
+            }
+        }
+    }
+
+    pub fn remove(&self, uri: &str) -> Result<()> {
+        let mut storage = self.0.lock().unwrap();
+        match &mut *storage {
+            Storage::Memory(map) => {
+                map.remove(uri);
+                Ok(())
+            }
+            Storage::Disk(dir) => {
+                let path = file_name(dir, uri);
+                let mut entries = read_file(&path)?;
+                entries.retain(|e| e.uri != uri);
+                if !entries.is_empty() {
+                    return write_file(&path, &entries);
+                }
+                match fs::remove_file(path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let mut storage = self.0.lock().unwrap();
+        match &mut *storage {
+            Storage::Memory(map) => map.clear(),
+            Storage::Disk(dir) => {
+                for file in fs::read_dir(dir)? {
+                    let path = file?.path();
+                    if path.extension().is_some_and(|ext| ext == "cache") {
+                        fs::remove_file(path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn entries(&self, uri: &str) -> Result<Vec<Entry>> {
+        let storage = self.0.lock().unwrap();
+        match &*storage {
+            Storage::Memory(map) => Ok(map.get(uri).cloned().unwrap_or_default()),
+            Storage::Disk(dir) => read_file(&file_name(dir, uri)),
+        }
+    }
+}
+
+// The name must stay the same across builds, so it can't use std's hasher.
+fn file_name(dir: &Path, uri: &str) -> PathBuf {
+    dir.join(format!("{}.cache", to_hex(&sha256(uri.as_bytes())[..16])))
+}
+
+fn read_file(path: &Path) -> Result<Vec<Entry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    while let Some(entry) = Entry::read_from(&mut reader)? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn write_file(path: &Path, entries: &[Entry]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for entry in entries {
+        entry.write_to(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Cache-Control directives with their optional arguments.
+pub fn directives(headers: &Headers) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all("Cache-Control")
+        .iter()
+        .flat_map(|v| v.split(','))
+        .filter_map(|directive| {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next()?.trim().to_ascii_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let value = parts.next().map(|v| v.trim().trim_matches('"').to_string());
+            Some((name, value))
+        })
+        .collect()
+}
+
+pub fn has(directives: &[(String, Option<String>)], name: &str) -> bool {
+    directives.iter().any(|(n, _)| n == name)
+}
+
+pub fn max_age(directives: &[(String, Option<String>)]) -> Option<u64> {
+    directives
+        .iter()
+        .find(|(n, _)| n == "max-age")
+        .and_then(|(_, v)| v.as_ref()?.parse().ok())
+}
+
+// Responses worth keeping: a cacheable status, no no-store and something that
+// makes them fresh or revalidatable. A Cache may be shared by many clients, so
+// private responses and answers to authorized requests are only kept when the
+// server allows it, RFC 9111, section 3.5.
+pub fn is_storable(request_headers: &Headers, response: &Response) -> bool {
+    let code = u16::from(response.status_code());
+    let headers = response.headers();
+    let directives = directives(headers);
+    let shareable = ["public", "s-maxage", "must-revalidate"]
+        .iter()
+        .any(|name| has(&directives, name));
+    matches!(code, 200 | 203 | 300 | 301 | 308 | 404 | 410)
+        && !has(&directives, "no-store")
+        && !has(&directives, "private")
+        && (shareable || !request_headers.contains_key("Authorization"))
+        && !has(&self::directives(request_headers), "no-store")
+        && !headers
+            .get_all("Vary")
+            .iter()
+            .any(|v| v.split(',').any(|v| v.trim() == "*"))
+        && (max_age(&directives).is_some()
+            || headers.contains_key("Expires")
+            || headers.contains_key("ETag")
+            || headers.contains_key("Last-Modified"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use std::env;
+
+    fn response(head: &str) -> Response {
+        Response::from_head(head.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn cache_freshness() {
+        let now = SystemTime::now();
+        let entry = Entry::new(
+            "http://a/",
+            &Headers::new(),
+            response("HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\nAge: 30\r\n\r\n"),
+            Vec::new(),
+            now,
+        );
+        assert!(entry.is_fresh(now + Duration::from_secs(29)));
+        assert!(!entry.is_fresh(now + Duration::from_secs(31)));
+
+        let entry = Entry::new(
+            "http://a/",
+            &Headers::new(),
+            response(
+                "HTTP/1.1 200 OK\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n\
+                 Expires: Sun, 06 Nov 1994 08:50:37 GMT\r\n\r\n",
+            ),
+            Vec::new(),
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap(),
+        );
+        let date = parse_http_date("Sun, 06 Nov 1994 08:50:00 GMT").unwrap();
+        assert!(entry.is_fresh(date));
+        assert!(!entry.is_fresh(date + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn cache_vary() {
+        let cache = Cache::memory();
+        let mut gzip = Headers::new();
+        gzip.insert("Accept-Encoding", "gzip");
+        let entry = Entry::new(
+            "http://a/",
+            &gzip,
+            response("HTTP/1.1 200 OK\r\nVary: Accept-Encoding\r\nETag: \"1\"\r\n\r\n"),
+            b"zipped".to_vec(),
+            SystemTime::now(),
+        );
+        cache.insert(entry).unwrap();
+        assert_eq!(cache.get("http://a/", &gzip).unwrap().body(), b"zipped");
+        assert!(cache.get("http://a/", &Headers::new()).is_none());
+    }
+
+    #[test]
+    fn cache_disk() {
+        let dir = env::temp_dir().join(format!("rp_cache_{}", crate::random::random_u64()));
+        let cache = Cache::disk(&dir).unwrap();
+        let entry = Entry::new(
+            "http://a/b",
+            &Headers::new(),
+            response("HTTP/1.1 200 OK\r\nETag: \"x\"\r\nContent-Length: 4\r\n\r\n"),
+            b"body".to_vec(),
+            UNIX_EPOCH + Duration::from_secs(1000),
+        );
+        cache.insert(entry.clone()).unwrap();
+        assert_eq!(
+            Cache::disk(&dir)
+                .unwrap()
+                .get("http://a/b", &Headers::new()),
+            Some(entry.clone())
+        );
+        let path = dir.join("1972c518da8e66a8782803107e466159.cache");
+        assert_eq!(file_name(&dir, "http://a/b"), path);
+
+        // Entries for another uri in the same file are kept.
+        let mut other = entry.clone();
+        other.uri = "http://c/d".to_string();
+        write_file(&path, &[other.clone(), entry.clone()]).unwrap();
+        cache.insert(entry).unwrap();
+        cache.remove("http://a/b").unwrap();
+        assert_eq!(read_file(&path).unwrap(), vec![other]);
+
+        cache.clear().unwrap();
+        assert!(cache.get("http://a/b", &Headers::new()).is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_shared() {
+        let mut auth = Headers::new();
+        auth.insert("Authorization", "Bearer x");
+        let fresh = response("HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\n\r\n");
+        assert!(is_storable(&Headers::new(), &fresh));
+        assert!(!is_storable(&auth, &fresh));
+        let public = response("HTTP/1.1 200 OK\r\nCache-Control: public, max-age=60\r\n\r\n");
+        assert!(is_storable(&auth, &public));
+        let private = response("HTTP/1.1 200 OK\r\nCache-Control: private, max-age=60\r\n\r\n");
+        assert!(!is_storable(&Headers::new(), &private));
+    }
+
+    #[test]
+    fn cache_client_fresh() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nCache-Control: max-age=60\r\nContent-Length: 5\r\n\r\nhello"
+                .to_vec(),
+            Vec::new(),
+        ]);
+        let cache = Cache::memory();
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "hello");
+        rx.recv().unwrap();
+
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        assert!(response.headers().contains_key("Age"));
+        assert_eq!(client.text().unwrap(), "hello");
+        drop(client);
+        assert!(rx.recv().map(|r| r.is_empty()).unwrap_or(true));
+    }
+
+    #[test]
+    fn cache_client_revalidate() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nCache-Control: no-cache\r\n\
+              Content-Length: 5\r\n\r\nhello"
+                .to_vec(),
+            b"HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nX-Checked: yes\r\n\r\n".to_vec(),
+        ]);
+        let cache = Cache::memory();
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "hello");
+        rx.recv().unwrap();
+
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        let response = client.send().unwrap();
+        assert_eq!(u16::from(response.status_code()), 200);
+        assert_eq!(response.headers().get("X-Checked"), Some("yes".to_string()));
+        assert_eq!(client.text().unwrap(), "hello");
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("If-None-Match: \"v1\"\r\n"));
+        assert!(!client.request().get_headers().contains_key("If-None-Match"));
+    }
+
+    #[test]
+    fn cache_client_replaced() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nCache-Control: no-cache\r\n\
+              Content-Length: 5\r\n\r\nhello"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nCache-Control: no-store\r\nContent-Length: 3\r\n\r\nnew".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nlater".to_vec(),
+        ]);
+        let cache = Cache::memory();
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "hello");
+        rx.recv().unwrap();
+
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "new");
+        rx.recv().unwrap();
+        assert!(cache.get(&uri, &Headers::new()).is_none());
+
+        let mut client = Client::new(uri.as_str()).cache(&cache).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "later");
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(!request.contains("If-None-Match"));
+    }
+}
@@ -0,0 +1,26 @@
+use std::fmt;
+use std::sync::Arc;
+
+pub struct Callback<T: ?Sized>(Arc<dyn Fn(&T) + Send + Sync>);
+
+impl<T: ?Sized> Callback<T> {
+    pub fn new<F: Fn(&T) + Send + Sync + 'static>(f: F) -> Callback<T> {
+        Callback(Arc::new(f))
+    }
+
+    pub fn call(&self, value: &T) {
+        (self.0)(value)
+    }
+}
+
+impl<T: ?Sized> Clone for Callback<T> {
+    fn clone(&self) -> Self {
+        Callback(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Callback<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
@@ -0,0 +1,193 @@
+use crate::error::{Error, Result};
+
+// Returns the charset parameter of a Content-Type value.
+pub fn from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        let value = value.trim().trim_matches('"').trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    })
+}
+
+// Detects a byte order mark, which takes precedence over any label.
+pub fn sniff_bom(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        Some("utf-8")
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+        Some("utf-16le")
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        Some("utf-16be")
+    } else {
+        None
+    }
+}
+
+// Looks for <meta charset> or <meta http-equiv content="...; charset=..."> in
+// the first 1024 bytes of an HTML document.
+pub fn sniff_meta(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head: String = head
+        .iter()
+        .map(|&b| (b as char).to_ascii_lowercase())
+        .collect();
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag = &rest[start + 5..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if let Some(pos) = tag.find("charset") {
+            let value = tag[pos + 7..].trim_start();
+            if let Some(value) = value.strip_prefix('=') {
+                let value = value.trim_start().trim_start_matches(['"', '\'']);
+                let end = value
+                    .find(|c: char| {
+                        c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace()
+                    })
+                    .unwrap_or(value.len());
+                if end > 0 {
+                    return Some(value[..end].to_string());
+                }
+            }
+        }
+        rest = &rest[start + 5..];
+    }
+    None
+}
+
+// Decodes a body with the charset of Content-Type. With sniffing on, a byte
+// order mark comes first and <meta charset> is the fallback. Unknown charsets
+// are read as UTF-8.
+pub fn decode_text(body: &[u8], content_type: Option<&str>, sniff: bool) -> String {
+    let charset = if sniff {
+        sniff_bom(body).map(|bom| bom.to_string())
+    } else {
+        None
+    }
+    .or_else(|| content_type.and_then(from_content_type))
+    .or_else(|| if sniff { sniff_meta(body) } else { None });
+    match charset.and_then(|charset| decode(body, &charset).ok()) {
+        Some(text) => text,
+        None => String::from_utf8_lossy(body).to_string(),
+    }
+}
+
+#[cfg(feature = "charset")]
+pub fn decode(bytes: &[u8], charset: &str) -> Result<String> {
+    let encoding = encoding_rs::Encoding::for_label(charset.trim().as_bytes())
+        .ok_or_else(|| Error::UnsupportedCharset(charset.to_string()))?;
+    let (text, _, _) = encoding.decode(bytes);
+    Ok(text.into_owned())
+}
+
+// Without the charset feature only UTF-8, UTF-16, ASCII and Latin-1 are known.
+#[cfg(not(feature = "charset"))]
+pub fn decode(bytes: &[u8], charset: &str) -> Result<String> {
+    match charset.trim().to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" | "unicode-1-1-utf-8" => {
+            let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+            Ok(String::from_utf8_lossy(bytes).into_owned())
+        }
+        "utf-16le" | "utf-16" => Ok(decode_utf16(bytes, u16::from_le_bytes, &[0xFF, 0xFE])),
+        "utf-16be" => Ok(decode_utf16(bytes, u16::from_be_bytes, &[0xFE, 0xFF])),
+        "us-ascii" | "ascii" | "iso-8859-1" | "iso8859-1" | "latin1" | "l1" => {
+            Ok(bytes.iter().map(|&b| b as char).collect())
+        }
+        _ => Err(Error::UnsupportedCharset(charset.to_string())),
+    }
+}
+
+#[cfg(not(feature = "charset"))]
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16, bom: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(bom).unwrap_or(bytes);
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| from_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+
+    #[test]
+    fn charset_content_type() {
+        assert_eq!(
+            from_content_type("text/html; Charset=\"Windows-1251\""),
+            Some("Windows-1251".to_string())
+        );
+        assert_eq!(from_content_type("text/html"), None);
+        assert_eq!(from_content_type("text/html; charset="), None);
+    }
+
+    #[test]
+    fn charset_sniff() {
+        assert_eq!(sniff_bom(b"\xEF\xBB\xBFhi"), Some("utf-8"));
+        assert_eq!(sniff_bom(b"hi"), None);
+        assert_eq!(
+            sniff_meta(b"<html><head><META CHARSET='koi8-r'></head>"),
+            Some("koi8-r".to_string())
+        );
+        assert_eq!(
+            sniff_meta(
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\">"
+            ),
+            Some("windows-1251".to_string())
+        );
+        assert_eq!(sniff_meta(b"<meta name=\"viewport\">"), None);
+    }
+
+    #[test]
+    fn charset_decode() {
+        assert_eq!(decode(b"caf\xE9", "ISO-8859-1").unwrap(), "café");
+        assert_eq!(decode(b"\xEF\xBB\xBFok", "utf-8").unwrap(), "ok");
+        assert_eq!(decode(b"\xFF\xFEo\0k\0", "utf-16le").unwrap(), "ok");
+    }
+
+    #[cfg(feature = "charset")]
+    #[test]
+    fn charset_decode_legacy() {
+        assert_eq!(
+            decode(b"\xCF\xF0\xE8\xE2\xE5\xF2", "windows-1251").unwrap(),
+            "Привет"
+        );
+        assert!(matches!(
+            decode(b"", "no-such-charset"),
+            Err(Error::UnsupportedCharset(_))
+        ));
+    }
+
+    #[test]
+    fn charset_client_text() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=iso-8859-1\r\n\
+              Content-Length: 4\r\n\r\ncaf\xE9"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 38\r\n\r\n\
+              <meta charset=\"iso-8859-1\"><p>caf\xE9</p>"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ncaf\xE9".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "café");
+
+        let mut client = Client::new(uri.as_str())
+            .sniff_charset(true)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert!(client.text().unwrap().ends_with("<p>café</p>"));
+
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text_with_charset("latin1").unwrap(), "café");
+    }
+}
@@ -1,9 +1,83 @@
+use std::io::{Cursor, ErrorKind, Read};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::auth::{Auth, DigestChallenge};
+use crate::body::Body;
+use crate::cache::{self, Cache, Entry};
+use crate::callback::Callback;
+use crate::charset;
 use crate::client_builder::ClientBuilder;
+use crate::cookie::CookieJar;
+use crate::encoding::{decoder, Decoder};
 use crate::error::{Error, Result};
+use crate::hash::to_hex;
+use crate::headers::{HeaderCase, Headers};
+use crate::limits::{LimitedReader, Limits};
+use crate::method::{IntoMethod, Method};
+use crate::progress::{response_range, Progress, ProgressReader};
+use crate::random::random_bytes;
+use crate::rate_limit::RateLimiter;
+use crate::redirect::{redirect_request, RedirectPolicy};
 use crate::request::Request;
 use crate::response::Response;
+use crate::retry::RetryPolicy;
+use crate::stream::Stream;
 use crate::transport::Transport;
+use crate::upload::Upload;
 use crate::uri::{IntoUri, Uri};
+use crate::version::Version;
+
+// What every request started with get, post, etc. begins with.
+#[derive(Debug, Clone, Default)]
+pub struct Defaults {
+    pub headers: Headers,
+    pub header_case: HeaderCase,
+    pub header_order: Vec<String>,
+    pub version: Version,
+    pub auth: Option<Auth>,
+    // Origin of the builder's uri, the only one its credentials are sent to.
+    pub origin: String,
+}
+
+impl Defaults {
+    // Builds a request with the default headers and the credentials that
+    // apply to the uri.
+    pub fn request(
+        &self,
+        method: Method,
+        uri: &Uri,
+        proxy: Option<&Uri>,
+    ) -> (Request, Option<Auth>) {
+        let same_origin = uri.origin() == self.origin;
+        let auth = match &self.auth {
+            Some(auth) if same_origin => Some(auth.clone()),
+            _ => Auth::from_uri(uri),
+        };
+        let mut headers = self.headers.clone();
+        if !same_origin {
+            headers.remove("Authorization");
+        }
+        if let Some(value) = auth.as_ref().and_then(Auth::header) {
+            if !headers.contains_key("Authorization") {
+                headers.insert("Authorization", &value);
+            }
+        }
+        if let Some(auth) = proxy.and_then(Uri::base64_auth) {
+            headers.insert("Proxy-Authorization", &format!("Basic {}", auth));
+        }
+        let mut request = Request::new(uri, proxy.is_some());
+        request
+            .method(method)
+            .headers(headers)
+            .header_case(self.header_case)
+            .header_order(self.header_order.clone())
+            .version(self.version);
+        (request, auth)
+    }
+}
 
 #[derive(Debug)]
 pub struct Client {
@@ -11,9 +85,34 @@ pub struct Client {
     uri: Uri,
     transport: Transport,
     response: Option<Response>,
+    proxy: Option<Uri>,
+    redirect: RedirectPolicy,
+    retry: RetryPolicy,
+    rate_limit: Option<RateLimiter>,
+    cookie_jar: Option<CookieJar>,
+    cookie: Option<String>,
+    cache: Option<Cache>,
+    // Credentials and the origin they were given for.
+    auth: Option<(Auth, String)>,
+    nonce_count: u32,
+    cached_body: Option<Vec<u8>>,
+    decompress: bool,
+    sniff_charset: bool,
+    expect_continue: Option<Duration>,
+    on_info: Option<Callback<Response>>,
+    limits: Limits,
+    upload: Option<Upload>,
+    on_download: Option<Callback<Progress>>,
+    on_upload: Option<Callback<Progress>>,
+    defaults: Defaults,
+    // Receives the connection once a response body has been read.
+    idle: Arc<Mutex<Option<Transport>>>,
+    // An invalid uri given to get, post, etc., reported by send.
+    error: Option<Error>,
 }
 
 impl Client {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<U: IntoUri>(uri: U) -> ClientBuilder {
         ClientBuilder::new().uri(uri)
     }
@@ -33,6 +132,27 @@ impl Client {
             uri,
             transport,
             response,
+            proxy: None,
+            redirect: RedirectPolicy::default(),
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            cookie_jar: None,
+            cookie: None,
+            cache: None,
+            auth: None,
+            nonce_count: 0,
+            cached_body: None,
+            decompress: true,
+            sniff_charset: false,
+            expect_continue: None,
+            on_info: None,
+            limits: Limits::default(),
+            upload: None,
+            on_download: None,
+            on_upload: None,
+            defaults: Defaults::default(),
+            idle: Arc::new(Mutex::new(None)),
+            error: None,
         }
     }
 
@@ -40,6 +160,195 @@ impl Client {
         self.request.clone()
     }
 
+    pub(crate) fn request_mut(&mut self) -> &mut Request {
+        &mut self.request
+    }
+
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    pub fn proxy(&self) -> Option<&Uri> {
+        self.proxy.as_ref()
+    }
+
+    pub(crate) fn set_proxy(&mut self, proxy: Option<Uri>) -> &mut Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn cookie_jar(&self) -> Option<&CookieJar> {
+        self.cookie_jar.as_ref()
+    }
+
+    pub(crate) fn set_cookie_jar(&mut self, jar: Option<CookieJar>) -> &mut Self {
+        self.cookie = self.request.get_headers().get("Cookie");
+        self.cookie_jar = jar;
+        self
+    }
+
+    pub fn cache(&mut self, cache: Option<Cache>) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn auth(&mut self, auth: Option<Auth>) -> &mut Self {
+        let origin = self.uri.origin();
+        self.auth = auth.map(|auth| (auth, origin));
+        self
+    }
+
+    pub fn redirect(&mut self, policy: RedirectPolicy) -> &mut Self {
+        self.redirect = policy;
+        self
+    }
+
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry = policy;
+        self
+    }
+
+    pub fn rate_limit(&mut self, limiter: Option<RateLimiter>) -> &mut Self {
+        self.rate_limit = limiter;
+        self
+    }
+
+    pub fn decompress(&mut self, enable: bool) -> &mut Self {
+        self.decompress = enable;
+        self
+    }
+
+    pub fn sniff_charset(&mut self, enable: bool) -> &mut Self {
+        self.sniff_charset = enable;
+        self
+    }
+
+    pub fn expect_continue(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.expect_continue = timeout;
+        self
+    }
+
+    pub fn on_info(&mut self, callback: Option<Callback<Response>>) -> &mut Self {
+        self.on_info = callback;
+        self
+    }
+
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn upload(&mut self, upload: Option<Upload>) -> &mut Self {
+        self.upload = upload;
+        self
+    }
+
+    pub fn on_download(&mut self, callback: Option<Callback<Progress>>) -> &mut Self {
+        self.on_download = callback;
+        self
+    }
+
+    pub fn on_upload(&mut self, callback: Option<Callback<Progress>>) -> &mut Self {
+        self.on_upload = callback;
+        self
+    }
+
+    pub(crate) fn set_defaults(&mut self, defaults: Defaults) -> &mut Self {
+        self.defaults = defaults;
+        self
+    }
+
+    pub fn get<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::GET, uri)
+    }
+
+    pub fn post<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::POST, uri)
+    }
+
+    pub fn put<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::PUT, uri)
+    }
+
+    pub fn patch<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::PATCH, uri)
+    }
+
+    pub fn delete<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::DELETE, uri)
+    }
+
+    pub fn head<U: IntoUri>(&mut self, uri: U) -> &mut Self {
+        self.method(Method::HEAD, uri)
+    }
+
+    // Starts a new request with the headers and settings the client was built
+    // with. The connection is reused when it points to the same origin and the
+    // previous response was read to its end, otherwise send reconnects.
+    pub fn method<T: IntoMethod, U: IntoUri>(&mut self, method: T, uri: U) -> &mut Self {
+        let (method, uri) = match (method.into_method(), uri.into_uri()) {
+            (Ok(method), Ok(uri)) => (method, uri),
+            (Err(e), _) | (_, Err(e)) => {
+                self.error = Some(e);
+                return self;
+            }
+        };
+        self.error = None;
+        let idle = match self.idle.lock() {
+            Ok(mut idle) => idle.take(),
+            Err(_) => None,
+        };
+        let idle = idle.or_else(|| self.finished_transport());
+        self.idle = Arc::new(Mutex::new(None));
+        self.transport = match idle {
+            Some(transport) if uri.origin() == self.uri.origin() => transport,
+            _ => Transport::None,
+        };
+        let (request, auth) = self.defaults.request(method, &uri, self.proxy.as_ref());
+        self.cookie = request.get_headers().get("Cookie");
+        self.request = request;
+        self.uri = uri;
+        self.auth(auth);
+        self.response = None;
+        self.cached_body = None;
+        self.upload = None;
+        self
+    }
+
+    pub fn header<T: ToString + ?Sized, U: ToString + ?Sized>(
+        &mut self,
+        key: &T,
+        value: &U,
+    ) -> &mut Self {
+        self.request.header(key, value);
+        self
+    }
+
+    pub fn body(&mut self, body: &[u8]) -> &mut Self {
+        self.request
+            .header("Content-Length", &body.len())
+            .remove_header("Transfer-Encoding")
+            .body(Some(body.to_vec()));
+        self.upload = None;
+        self
+    }
+
+    // The connection of a response without a body is ready for the next
+    // request right away.
+    fn finished_transport(&mut self) -> Option<Transport> {
+        let response = self.response.as_ref()?;
+        if !is_persistent(&self.request, response) {
+            return None;
+        }
+        let transport = mem::take(&mut self.transport);
+        let body = Body::new(transport, response, self.request.get_method()).ok()?;
+        if body.is_done() {
+            Some(body.into_transport())
+        } else {
+            None
+        }
+    }
+
     pub fn send_request(&mut self) -> Result<()> {
         match self.transport {
             Transport::Proxy(ref mut proxy) => proxy.send_request(&self.request.msg()),
@@ -48,44 +357,914 @@ impl Client {
         }
     }
 
+    // Sends the request, connecting first if there is no open connection, and
+    // retries transient failures as the retry policy allows.
     pub fn send(&mut self) -> Result<Response> {
-        self.send_request()?;
-        let response = match self.transport {
-            Transport::Proxy(ref mut proxy) => proxy.get_response(),
-            Transport::Stream(ref mut stream) => stream.get_response(),
-            Transport::None => return Err(Error::WrongHttp),
-        }?;
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        // A streamed body is consumed by the first attempt.
+        let replayable = self.upload.is_none();
+        let mut retry = 0;
+        loop {
+            let result = if retry > 0 || matches!(self.transport, Transport::None) {
+                self.reconnect().and_then(|_| self.send_once())
+            } else {
+                self.send_once()
+            };
+            retry += 1;
+            let delay = match self.retry.delay(&result, self.request.get_method(), retry) {
+                Some(delay) if replayable => delay,
+                _ => return result,
+            };
+            if let Some(proxy) = self.retry.next_proxy(self.proxy.as_ref()) {
+                self.switch_proxy(proxy);
+            }
+            thread::sleep(delay);
+        }
+    }
+
+    pub(crate) fn switch_proxy(&mut self, proxy: Uri) {
+        match proxy.base64_auth() {
+            Some(auth) => self
+                .request
+                .header("Proxy-Authorization", &format!("Basic {}", auth)),
+            None => self.request.remove_header("Proxy-Authorization"),
+        };
+        // The request target form depends on whether a proxy is used.
+        self.request.set_using_proxy(true).uri(&self.uri);
+        self.proxy = Some(proxy);
+    }
+
+    fn send_once(&mut self) -> Result<Response> {
+        self.cached_body = None;
+        let cache = match &self.cache {
+            Some(cache) => cache.clone(),
+            None => return self.fetch(),
+        };
+        match self.request.get_method() {
+            Method::GET => self.send_cached(&cache),
+            Method::HEAD | Method::OPTIONS | Method::TRACE => self.fetch(),
+            // Unsafe methods invalidate the stored response, RFC 7234, section 4.4.
+            _ => {
+                let key = self.uri.without_fragment();
+                let response = self.fetch()?;
+                if u16::from(response.status_code()) < 400 {
+                    cache.remove(&key)?;
+                }
+                Ok(response)
+            }
+        }
+    }
+
+    // Serves a fresh stored response, revalidates a stale one and stores what
+    // the server sends when it's cacheable.
+    fn send_cached(&mut self, cache: &Cache) -> Result<Response> {
+        let key = self.uri.without_fragment();
+        let headers = self.request.get_headers().clone();
+        let directives = cache::directives(&headers);
+        if cache::has(&directives, "no-store") {
+            return self.fetch();
+        }
+        let revalidate =
+            cache::has(&directives, "no-cache") || cache::max_age(&directives) == Some(0);
+        let entry = match cache.get(&key, &headers) {
+            Some(entry) if !revalidate && entry.is_fresh(SystemTime::now()) => {
+                return Ok(self.serve_cached(entry));
+            }
+            entry => entry,
+        };
+        let mut conditional = Vec::new();
+        if let Some(entry) = &entry {
+            if !headers.contains_key("If-None-Match") {
+                if let Some(etag) = entry.etag() {
+                    conditional.push(("If-None-Match", etag));
+                }
+            }
+            if !headers.contains_key("If-Modified-Since") {
+                if let Some(modified) = entry.last_modified() {
+                    conditional.push(("If-Modified-Since", modified));
+                }
+            }
+        }
+        for (name, value) in &conditional {
+            self.request.header(*name, value);
+        }
+        let response = self.fetch();
+        for (name, _) in &conditional {
+            self.request.remove_header(*name);
+        }
+        let response = response?;
+        let now = SystemTime::now();
+        if !response.redirects().is_empty() {
+            return Ok(response);
+        }
+        match entry {
+            Some(mut entry)
+                if u16::from(response.status_code()) == 304 && !conditional.is_empty() =>
+            {
+                entry.update(&response, now);
+                cache.insert(entry.clone())?;
+                Ok(self.serve_cached(entry))
+            }
+            _ if cache::is_storable(&headers, &response) => {
+                let body = self.raw_body(&response)?;
+                cache.insert(Entry::new(
+                    &key,
+                    &headers,
+                    response.clone(),
+                    body.clone(),
+                    now,
+                ))?;
+                self.cached_body = Some(body);
+                Ok(response)
+            }
+            // The stored response was replaced by one that can't be kept. A
+            // server error doesn't replace it.
+            Some(_) if u16::from(response.status_code()) < 500 => {
+                cache.remove(&key)?;
+                Ok(response)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    fn serve_cached(&mut self, entry: Entry) -> Response {
+        let age = entry.age(SystemTime::now()).as_secs();
+        let mut response = entry.response().clone();
+        response.headers_mut().insert("Age", &age);
+        self.cached_body = Some(entry.body().to_vec());
         self.response = Some(response.clone());
-        Ok(response)
+        response
+    }
+
+    // Reads the body as sent, without decoding, so that it can be stored.
+    fn raw_body(&mut self, response: &Response) -> Result<Vec<u8>> {
+        let transport = mem::take(&mut self.transport);
+        let mut body = Body::new(transport, response, self.request.get_method())?;
+        let mut raw = Vec::new();
+        match self.limits.body_bytes {
+            Some(max) => LimitedReader::new(body, max).read_to_end(&mut raw)?,
+            None => body.read_to_end(&mut raw)?,
+        };
+        Ok(raw)
+    }
+
+    fn fetch(&mut self) -> Result<Response> {
+        let mut redirects = Vec::new();
+        loop {
+            self.add_cookies();
+            let streamed = self.upload.is_some();
+            let mut response = self.exchange()?;
+            if !streamed && self.authorize(&response) {
+                self.reconnect()?;
+                response = self.exchange()?;
+            }
+            self.store_cookies(&response);
+            if self.follow_redirect(&response, &mut redirects, streamed)? {
+                self.transport = self.connect(&self.uri)?;
+            } else {
+                response.set_redirects(redirects);
+                self.response = Some(response.clone());
+                return Ok(response);
+            }
+        }
+    }
+
+    pub(crate) fn store_cookies(&self, response: &Response) {
+        if let Some(jar) = &self.cookie_jar {
+            jar.store(&self.uri, &response.cookies());
+        }
+    }
+
+    // Moves the request on to the location of a redirect the policy allows,
+    // adding the previous uri to `redirects`. Returns false when the response
+    // is the final one.
+    pub(crate) fn follow_redirect(
+        &mut self,
+        response: &Response,
+        redirects: &mut Vec<Uri>,
+        streamed: bool,
+    ) -> Result<bool> {
+        let location = match self.redirect.location(response, &self.uri, redirects)? {
+            Some(location) => location,
+            None => return Ok(false),
+        };
+        let request = redirect_request(&self.request, response, &self.uri, &location);
+        // A streamed body can't be sent again, so a redirect that keeps the
+        // method (307, 308) is returned to the caller instead.
+        if streamed && request.get_method() == self.request.get_method() {
+            return Ok(false);
+        }
+        self.request = request;
+        redirects.push(mem::replace(&mut self.uri, location));
+        Ok(true)
     }
 
-    fn content_len(&self) -> Result<usize> {
-        if let Some(response) = &self.response {
-            response.content_len()
+    fn exchange(&mut self) -> Result<Response> {
+        self.request.validate()?;
+        let has_body =
+            self.upload.is_some() || self.request.get_body().is_some_and(|b| !b.is_empty());
+        match self.expect_continue {
+            Some(timeout) if has_body => {
+                self.request.header("Expect", "100-continue");
+                if let Some(response) = self.wait_continue(timeout)? {
+                    return Ok(response);
+                }
+                self.send_body()?;
+            }
+            expect => {
+                if expect.is_some() {
+                    self.request.remove_header("Expect");
+                }
+                if self.upload.is_some() || (self.on_upload.is_some() && has_body) {
+                    Stream::send_msg(self.transport.stream_mut()?, &self.request.msg_head())?;
+                    self.send_body()?;
+                } else {
+                    self.send_request()?;
+                }
+            }
+        }
+        self.read_response()
+    }
+
+    // Sends the head and waits for the go-ahead. Returns the final response if
+    // the server answered before the body was sent, e.g. with 417 or 401.
+    fn wait_continue(&mut self, timeout: Duration) -> Result<Option<Response>> {
+        let stream = self.transport.stream_mut()?;
+        Stream::send_msg(stream, &self.request.msg_head())?;
+        stream.set_read_timeout(Some(timeout))?;
+        let interim = Stream::read_head_with(stream, &self.limits);
+        stream.set_read_timeout(None)?;
+        match interim {
+            Ok(response) if !is_interim(&response) => Ok(Some(response)),
+            Ok(response) => {
+                if let Some(callback) = &self.on_info {
+                    callback.call(&response);
+                }
+                Ok(None)
+            }
+            // No answer in time, send the body anyway as RFC 7231 allows.
+            Err(Error::Io(ref e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn send_body(&mut self) -> Result<()> {
+        let stream = self.transport.stream_mut()?;
+        let upload = match (self.upload.take(), self.request.get_body()) {
+            (Some(upload), _) => upload,
+            (None, Some(body)) if !body.is_empty() => match &self.on_upload {
+                Some(_) => Upload::new(Cursor::new(body.to_vec()), Some(body.len() as u64)),
+                None => return Stream::send_msg(stream, body),
+            },
+            _ => return Ok(()),
+        };
+        match &self.on_upload {
+            Some(callback) => upload.progress(callback.clone()).write_to(stream)?,
+            None => upload.write_to(stream)?,
+        };
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> Result<Response> {
+        loop {
+            let response = Stream::read_head_with(self.transport.stream_mut()?, &self.limits)?;
+            if !is_interim(&response) {
+                return Ok(response);
+            }
+            if let Some(callback) = &self.on_info {
+                callback.call(&response);
+            }
+        }
+    }
+
+    // Answers a Digest challenge in a 401 response. Returns true when the
+    // request should be sent again with the new Authorization header.
+    pub(crate) fn authorize(&mut self, response: &Response) -> bool {
+        if u16::from(response.status_code()) != 401 {
+            return false;
+        }
+        let (username, password) = match &self.auth {
+            Some((Auth::Digest(username, password), origin)) if *origin == self.uri.origin() => {
+                (username.clone(), password.clone())
+            }
+            _ => return false,
+        };
+        let challenge = match DigestChallenge::from_response(response) {
+            Some(challenge) => challenge,
+            None => return false,
+        };
+        self.nonce_count += 1;
+        let authorization = challenge.authorization(
+            &username,
+            &password,
+            self.request.get_method().as_str(),
+            self.request.get_request_uri(),
+            self.request.get_body().unwrap_or_default(),
+            &to_hex(&random_bytes(16)),
+            self.nonce_count,
+        );
+        match authorization {
+            Some(authorization) => {
+                self.request.header("Authorization", &authorization);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Opens a new connection to the current uri, e.g. to retry a request.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.transport = self.connect(&self.uri)?;
+        self.response = None;
+        Ok(())
+    }
+
+    pub(crate) fn add_cookies(&mut self) {
+        if let Some(jar) = &self.cookie_jar {
+            let cookie = match (&self.cookie, jar.header(&self.uri)) {
+                (Some(user), Some(jar)) => Some(format!("{}; {}", user, jar)),
+                (user, jar) => user.clone().or(jar),
+            };
+            match cookie {
+                Some(cookie) => self.request.header("Cookie", &cookie),
+                None => self.request.remove_header("Cookie"),
+            };
+        }
+    }
+
+    fn connect(&self, uri: &Uri) -> Result<Transport> {
+        if let Some(limiter) = &self.rate_limit {
+            limiter.acquire(uri, self.proxy.as_ref())?;
+        }
+        match &self.proxy {
+            Some(proxy) => Transport::proxy(proxy, uri),
+            None => Transport::stream(uri),
+        }
+    }
+
+    pub fn body_reader(&mut self) -> Result<Decoder> {
+        let response = self.response.as_ref().ok_or(Error::EmptyResponse)?;
+        let (body, content_len): (Decoder, Option<u64>) = match self.cached_body.take() {
+            Some(body) => {
+                let len = body.len() as u64;
+                (Box::new(Cursor::new(body)), Some(len))
+            }
+            None => {
+                let transport = mem::take(&mut self.transport);
+                let mut body = Body::new(transport, response, self.request.get_method())?;
+                if is_persistent(&self.request, response) {
+                    body = body.release_to(self.idle.clone());
+                }
+                let len = body.content_len();
+                (Box::new(body), len)
+            }
+        };
+        let limit = self.limits.body_bytes;
+        if let (Some(max), Some(len)) = (limit, content_len) {
+            if len > max {
+                return Err(Error::BodyTooLarge(max));
+            }
+        }
+        let body: Decoder = match &self.on_download {
+            Some(callback) => {
+                let (offset, total) = response_range(response, content_len);
+                Box::new(ProgressReader::new(body, callback.clone(), total).offset(offset))
+            }
+            None => body,
+        };
+        let reader = if self.decompress {
+            let content_encoding = response.headers().get("Content-Encoding");
+            decoder(body, content_encoding.as_deref())?
         } else {
-            Err(Error::EmptyResponse)
+            body
+        };
+        match limit {
+            Some(max) => Ok(Box::new(LimitedReader::new(reader, max))),
+            None => Ok(reader),
         }
     }
 
     pub fn get_body(&mut self) -> Result<Vec<u8>> {
-        let content_len = self.content_len()?;
-        match self.transport {
-            Transport::Proxy(ref mut proxy) => proxy.get_body(content_len),
-            Transport::Stream(ref mut stream) => stream.get_body(content_len),
-            Transport::None => Err(Error::WrongHttp),
-        }
+        let mut body = Vec::new();
+        self.body_reader()?.read_to_end(&mut body)?;
+        Ok(body)
     }
 
+    // Decodes the body with the charset of the response, see
+    // charset::decode_text.
     pub fn text(&mut self) -> Result<String> {
         let body = self.get_body()?;
-        Ok(String::from_utf8_lossy(&body).to_string())
+        let content_type = self
+            .response
+            .as_ref()
+            .and_then(|response| response.headers().get("Content-Type"));
+        Ok(charset::decode_text(
+            &body,
+            content_type.as_deref(),
+            self.sniff_charset,
+        ))
+    }
+
+    pub fn text_with_charset(&mut self, charset: &str) -> Result<String> {
+        let body = self.get_body()?;
+        charset::decode(&body, charset)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T> {
+        let body = self.get_body()?;
+        let status = match &self.response {
+            Some(response) => response.status_code(),
+            None => return Err(Error::EmptyResponse),
+        };
+        serde_json::from_slice(&body).map_err(|e| Error::JsonDecode(status, e))
+    }
+}
+
+// What AsyncClient reads of the settings.
+#[cfg(feature = "tokio")]
+impl Client {
+    pub(crate) fn get_request(&self) -> &Request {
+        &self.request
+    }
+
+    pub(crate) fn get_retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub(crate) fn get_rate_limit(&self) -> Option<&RateLimiter> {
+        self.rate_limit.as_ref()
+    }
+
+    pub(crate) fn get_limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub(crate) fn get_decompress(&self) -> bool {
+        self.decompress
+    }
+
+    pub(crate) fn get_sniff_charset(&self) -> bool {
+        self.sniff_charset
+    }
+
+    pub(crate) fn get_on_info(&self) -> Option<&Callback<Response>> {
+        self.on_info.as_ref()
+    }
+
+    pub(crate) fn take_upload(&mut self) -> Option<Upload> {
+        self.upload.take()
+    }
+
+    pub(crate) fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
     }
 }
 
+// Whether the connection stays open for the next request once the body of
+// the response has been read.
+pub(crate) fn is_persistent(request: &Request, response: &Response) -> bool {
+    let close = |headers: &Headers| {
+        headers.get_all("Connection").iter().any(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("close"))
+        })
+    };
+    response.version() == "HTTP/1.1" && !close(request.get_headers()) && !close(response.headers())
+}
+
+// 101 Switching Protocols ends the HTTP exchange, every other 1xx is followed
+// by the final response.
+pub(crate) fn is_interim(response: &Response) -> bool {
+    let code = response.status_code();
+    code.is_info() && u16::from(code) != 101
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn client_chunked() {
+        let (uri, rx) = crate::tests::serve(vec![b"HTTP/1.1 200 OK\r\n\
+              Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n"
+            .to_vec()]);
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        assert_eq!(client.text().unwrap(), "hello");
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.starts_with("GET "));
+    }
+
+    #[test]
+    fn client_interim() {
+        use std::sync::{Arc, Mutex};
+
+        let (uri, _rx) = crate::tests::serve(vec![b"HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 103 Early Hints\r\nLink: </style.css>\r\n\r\n\
+              HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"
+            .to_vec()]);
+        let codes = Arc::new(Mutex::new(Vec::new()));
+        let seen = codes.clone();
+        let mut client = Client::new(uri.as_str())
+            .on_info(move |r| seen.lock().unwrap().push(u16::from(r.status_code())))
+            .build()
+            .unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        assert_eq!(client.text().unwrap(), "ok");
+        assert_eq!(*codes.lock().unwrap(), vec![100, 103]);
+    }
+
+    #[test]
+    fn client_expect_continue() {
+        use std::io::Write;
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                head.push(byte[0]);
+            }
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+            let mut body = [0u8; 4];
+            stream.read_exact(&mut body).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (String::from_utf8(head).unwrap(), body)
+        });
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .body(b"data")
+            .expect_continue(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        let (head, body) = server.join().unwrap();
+        assert!(head.to_lowercase().contains("expect: 100-continue"));
+        assert_eq!(&body, b"data");
+    }
+
+    #[test]
+    fn client_expect_continue_timeout() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .body(b"data")
+            .expect_continue(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert!(String::from_utf8(rx.recv().unwrap())
+            .unwrap()
+            .ends_with("\r\n\r\ndata"));
+    }
+
+    #[test]
+    fn client_reuse_connection() {
+        use std::io::Write;
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut heads = Vec::new();
+            for body in &["one", "two"] {
+                let head = crate::limits::read_head(&mut stream, &Limits::default()).unwrap();
+                heads.push(String::from_utf8(head).unwrap());
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{}", body);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            heads
+        });
+        let mut client = Client::new(format!("{}/a", uri))
+            .header("Connection", "keep-alive")
+            .header("X-Shared", "yes")
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "one");
+        client.get(format!("{}/b", uri)).send().unwrap();
+        assert_eq!(client.text().unwrap(), "two");
+
+        let heads = server.join().unwrap();
+        assert!(heads[1].starts_with("GET "));
+        assert!(heads[1].contains("/b HTTP/1.1\r\n"));
+        assert!(heads[1].contains("X-Shared: yes\r\n"));
+    }
+
+    #[test]
+    fn client_new_request() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none".to_vec(),
+            b"HTTP/1.1 201 Created\r\nContent-Length: 3\r\n\r\ntwo".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .header("X-Shared", "yes")
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "one");
+        let response = client.post(uri.as_str()).body(b"data").send().unwrap();
+        assert_eq!(u16::from(response.status_code()), 201);
+        assert_eq!(client.text().unwrap(), "two");
+        rx.recv().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.starts_with("POST "));
+        assert!(request.contains("X-Shared: yes\r\n"));
+        assert!(request.ends_with("Content-Length: 4\r\n\r\ndata"));
+
+        assert!(client.get("not a uri").send().is_err());
+    }
+
+    #[test]
+    fn client_header_case() {
+        use crate::headers::HeaderCase;
+
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .header("x-custom-ID", "1")
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("\r\nHost: "));
+        assert!(request.contains("\r\nx-custom-ID: 1\r\n"));
+
+        let mut client = Client::new(uri.as_str())
+            .header("accept", "*/*")
+            .header("user-agent", "Mozilla/5.0")
+            .header_case(HeaderCase::Title)
+            .header_order(&["Host", "User-Agent", "Accept", "Connection"])
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        let lines: Vec<&str> = request.split("\r\n").skip(1).take(4).collect();
+        assert!(lines[0].starts_with("Host: 127.0.0.1:"));
+        assert_eq!(
+            &lines[1..],
+            &[
+                "User-Agent: Mozilla/5.0",
+                "Accept: */*",
+                "Connection: Close"
+            ]
+        );
+    }
+
+    #[test]
+    fn client_header_injection() {
+        let (uri, _rx) = crate::tests::serve(Vec::new());
+        let mut client = Client::new(uri.as_str())
+            .header("X-Scraped", "value\r\nX-Injected: 1")
+            .build()
+            .unwrap();
+        match client.send() {
+            Err(Error::InvalidHeaderValue(name)) => assert_eq!(name, "X-Scraped"),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn client_limits() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789".to_vec(),
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n"
+                .to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str()).max_headers(2).build().unwrap();
+        assert!(matches!(client.send(), Err(Error::TooManyHeaders(2))));
+
+        let mut client = Client::new(uri.as_str()).max_body_bytes(8).build().unwrap();
+        client.send().unwrap();
+        assert!(matches!(client.get_body(), Err(Error::BodyTooLarge(8))));
+
+        let mut client = Client::new(uri.as_str()).max_body_bytes(8).build().unwrap();
+        client.send().unwrap();
+        assert!(matches!(client.get_body(), Err(Error::BodyTooLarge(8))));
+    }
+
+    #[test]
+    fn client_upload() {
+        use std::io::Cursor;
+
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .put(uri.as_str())
+            .body_reader_with_len(Cursor::new(b"streamed".to_vec()), 8)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.to_lowercase().contains("content-length: 8\r\n"));
+        assert!(request.ends_with("\r\n\r\nstreamed"));
+
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .body_reader(Cursor::new(b"chunked".to_vec()))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request
+            .to_lowercase()
+            .contains("transfer-encoding: chunked\r\n"));
+        assert!(!request.to_lowercase().contains("content-length"));
+        assert!(request.ends_with("\r\n\r\n7\r\nchunked\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn client_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec(),
+        ]);
+        let downloaded = Arc::new(Mutex::new(Vec::new()));
+        let uploaded = Arc::new(Mutex::new(Vec::new()));
+        let (down, up) = (downloaded.clone(), uploaded.clone());
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .body(b"data")
+            .on_download_progress(move |p| down.lock().unwrap().push((p.bytes, p.total)))
+            .on_upload_progress(move |p| up.lock().unwrap().push((p.bytes, p.total)))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "hello");
+        assert_eq!(downloaded.lock().unwrap().last(), Some(&(5, Some(5))));
+        assert_eq!(*uploaded.lock().unwrap(), vec![(4, Some(4))]);
+    }
+
+    #[test]
+    fn client_query_form() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .post(format!("{}/search?x=1", uri))
+            .query(&[("q", "a b"), ("lang", "ru&en")])
+            .form(&[("name", "John Doe"), ("note", "100%")])
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("/search?x=1&q=a+b&lang=ru%26en HTTP/1.1"));
+        assert!(request
+            .to_lowercase()
+            .contains("content-type: application/x-www-form-urlencoded"));
+        assert!(request.ends_with("\r\n\r\nname=John+Doe&note=100%25"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn client_json() {
+        use serde_json::{json, Value};
+
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 24\r\n\r\n{\"id\":2,\"name\":\"second\"}".to_vec(),
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .json(&json!({"id": 1, "name": "first"}))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let received: Value = client.json().unwrap();
+        assert_eq!(received, json!({"id": 2, "name": "second"}));
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request
+            .to_lowercase()
+            .contains("content-type: application/json"));
+        assert!(request.ends_with("{\"id\":1,\"name\":\"first\"}"));
+
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        client.send().unwrap();
+        match client.json::<Value>() {
+            Err(Error::JsonDecode(status, _)) => assert_eq!(u16::from(status), 404),
+            r => panic!("{:?}", r),
+        }
+
+        // Map keys must be strings.
+        let value: std::collections::HashMap<Vec<u8>, u8> =
+            vec![(vec![1], 1)].into_iter().collect();
+        match Client::new(uri.as_str()).json(&value).build() {
+            Err(Error::JsonEncode(_)) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn client_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let body = encoder.finish().unwrap();
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend(&body);
+        let (uri, rx) = crate::tests::serve(vec![response.clone(), response]);
+
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.text().unwrap(), "hello");
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.to_lowercase().contains("accept-encoding: gzip"));
+
+        let mut client = Client::new(uri.as_str()).decompress(false).build().unwrap();
+        client.send().unwrap();
+        assert_eq!(client.get_body().unwrap(), body);
+    }
+
+    // Without the codec the body is returned as sent.
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn client_gzip_raw() {
+        let body = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcbH\xcd\xc9\xc9\x07\x00\x86\xa6\x106\x05\x00\x00\x00";
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend(&body[..]);
+        let (uri, rx) = crate::tests::serve(vec![response]);
+
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        let response = client.send().unwrap();
+        assert_eq!(response.headers().get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(client.get_body().unwrap(), &body[..]);
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(!request.to_lowercase().contains("accept-encoding"));
+    }
+
+    #[test]
+    fn client_cookie_jar() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next\r\n\
+              Set-Cookie: a=1; Path=/\r\nSet-Cookie: b=2; Path=/\r\n\
+              Content-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let jar = CookieJar::new();
+        let mut client = Client::new(uri.as_str())
+            .cookie_jar(&jar)
+            .redirect(crate::redirect::RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        rx.recv().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("a=1; b=2"));
+
+        let mut client = Client::new(uri.as_str())
+            .header("Cookie", "c=3")
+            .cookie_jar(&jar)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("c=3; a=1; b=2"));
+    }
+
     #[test]
     fn client_http() {
         let mut client = Client::new("http://api.ipify.org").build().unwrap();
@@ -1,62 +1,216 @@
-// use std::time::Duration;
+use std::io::Read;
+use std::time::Duration;
 
-use crate::client::Client;
+#[cfg(feature = "tokio")]
+use crate::async_client::AsyncClient;
+use crate::auth::Auth;
+use crate::cache::Cache;
+use crate::callback::Callback;
+use crate::client::{Client, Defaults};
+use crate::cookie::CookieJar;
+use crate::encoding::accept_encoding;
 use crate::error::{Error, Result};
-use crate::headers::Headers;
+use crate::headers::{HeaderCase, Headers};
+use crate::limits::Limits;
 use crate::method::{IntoMethod, Method};
+use crate::multipart::Form;
+use crate::percent::encode_pairs;
+use crate::progress::Progress;
+use crate::rate_limit::RateLimiter;
+use crate::redirect::RedirectPolicy;
 use crate::request::Request;
+use crate::response::Response;
+use crate::retry::{is_retryable_error, RetryPolicy};
 use crate::transport::Transport;
+use crate::upload::Upload;
 use crate::uri::{IntoUri, Uri};
 use crate::version::{IntoVersion, Version};
 
 #[derive(Debug)]
 pub struct ClientBuilder {
     uri: Option<Uri>,
+    query: Vec<String>,
     headers: Headers,
+    header_case: HeaderCase,
+    header_order: Vec<String>,
     method: Method,
     version: Version,
     body: Option<Vec<u8>>,
+    upload: Option<Upload>,
     referer: bool,
     proxy: Option<Uri>,
     nodelay: bool,
+    redirect: RedirectPolicy,
+    retry: RetryPolicy,
+    rate_limit: Option<RateLimiter>,
+    cookie_jar: Option<CookieJar>,
+    cache: Option<Cache>,
+    auth: Option<Auth>,
+    decompress: bool,
+    sniff_charset: bool,
+    expect_continue: Option<Duration>,
+    on_info: Option<Callback<Response>>,
+    limits: Limits,
+    on_download: Option<Callback<Progress>>,
+    on_upload: Option<Callback<Progress>>,
+    // A setting that failed, reported by build.
+    error: Option<Error>,
     // timeout: Option<Duration>,
     // connect_timeout: Option<Duration>,
 }
 
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder::new()
+    }
+}
+
 impl ClientBuilder {
     pub fn new() -> Self {
         let headers = Headers::new();
         ClientBuilder {
             uri: None,
+            query: Vec::new(),
             headers,
+            header_case: HeaderCase::default(),
+            header_order: Vec::new(),
             method: Method::GET,
             version: Version::Http11,
             body: None,
+            upload: None,
             referer: true,
             proxy: None,
             nodelay: false,
+            redirect: RedirectPolicy::default(),
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            cookie_jar: None,
+            cache: None,
+            auth: None,
+            decompress: true,
+            sniff_charset: false,
+            expect_continue: None,
+            on_info: None,
+            limits: Limits::default(),
+            on_download: None,
+            on_upload: None,
+            error: None,
             // timeout: None,
             // connect_timeout: None,
         }
     }
 
     pub fn build(self) -> Result<Client> {
-        let uri = self.uri.ok_or(Error::EmptyUri)?;
+        let retries = self.retry.retries();
+        let mut client = self.into_client()?;
+        match client.reconnect() {
+            Ok(()) => Ok(client),
+            // A failed connection is left to the retries in Client::send.
+            Err(e) if retries > 0 && is_retryable_error(&e) => Ok(client),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Builds a client that does its I/O on tokio. Like build, it connects
+    // right away.
+    #[cfg(feature = "tokio")]
+    pub async fn build_async(self) -> Result<AsyncClient> {
+        let unsupported = [
+            (self.cache.is_some(), "cache"),
+            (self.expect_continue.is_some(), "expect_continue"),
+            (self.on_download.is_some(), "on_download"),
+            (self.on_upload.is_some(), "on_upload"),
+        ];
+        if let Some((_, setting)) = unsupported.iter().find(|(set, _)| *set) {
+            return Err(Error::UnsupportedAsync(setting));
+        }
+        let retries = self.retry.retries();
+        let mut client = AsyncClient::from(self.into_client()?)?;
+        match client.reconnect().await {
+            Ok(()) => Ok(client),
+            Err(e) if retries > 0 && is_retryable_error(&e) => Ok(client),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn into_client(self) -> Result<Client> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let mut uri = self.uri.ok_or(Error::EmptyUri)?;
+        if !self.query.is_empty() {
+            uri = uri.append_query(&self.query.join("&"))?;
+        }
         let mut headers = self.headers;
-        let transport = if let Some(proxy) = &self.proxy {
-            if let Some(auth) = proxy.base64_auth() {
-                headers.insert("Proxy-Authorization", format!("Basic {}", auth).as_str());
-            };
-            Transport::proxy(&proxy, &uri)?
-        } else {
-            Transport::stream(&uri)?
+        if let Some(encoding) = accept_encoding() {
+            if headers.get("Accept-Encoding").is_none() {
+                headers.insert("Accept-Encoding", &encoding);
+            }
+        }
+        let mut default_headers = headers.clone();
+        default_headers.remove("Content-Length");
+        default_headers.remove("Transfer-Encoding");
+        if self.body.is_some() || self.upload.is_some() {
+            default_headers.remove("Content-Type");
+        }
+        let defaults = Defaults {
+            headers: default_headers,
+            header_case: self.header_case,
+            header_order: self.header_order.clone(),
+            version: self.version,
+            auth: self.auth.clone().or_else(|| Auth::from_uri(&uri)),
+            origin: uri.origin(),
         };
+        // Userinfo in the target stands in for credentials not given otherwise.
+        let auth = self.auth.or_else(|| Auth::from_uri(&uri));
+        if let Some(value) = auth.as_ref().and_then(Auth::header) {
+            if !headers.contains_key("Authorization") {
+                headers.insert("Authorization", &value);
+            }
+        }
+        if !headers.contains_key("Content-Length") && !headers.contains_key("Transfer-Encoding") {
+            match (&self.upload, &self.body) {
+                (Some(upload), _) => match upload.content_len() {
+                    Some(len) => headers.insert("Content-Length", &len),
+                    None => headers.insert("Transfer-Encoding", "chunked"),
+                },
+                (None, Some(body)) => headers.insert("Content-Length", &body.len()),
+                (None, None) => None,
+            };
+        }
+        if let Some(auth) = self.proxy.as_ref().and_then(Uri::base64_auth) {
+            headers.insert("Proxy-Authorization", format!("Basic {}", auth).as_str());
+        }
         let mut request = Request::new(&uri, self.proxy.is_some());
         request.method(self.method);
         request.headers(headers);
+        request.header_case(self.header_case);
+        request.header_order(self.header_order);
         request.version(self.version);
         request.body(self.body);
-        Ok(Client::from(request, uri, transport, None))
+        let mut client = Client::from(request, uri, Transport::None, None);
+        client
+            .set_proxy(self.proxy)
+            .set_cookie_jar(self.cookie_jar)
+            .cache(self.cache)
+            .auth(auth)
+            .redirect(self.redirect)
+            .retry(self.retry)
+            .rate_limit(self.rate_limit)
+            .decompress(self.decompress)
+            .sniff_charset(self.sniff_charset)
+            .expect_continue(self.expect_continue)
+            .on_info(self.on_info)
+            .limits(self.limits)
+            .upload(self.upload)
+            .on_download(self.on_download)
+            .on_upload(self.on_upload)
+            .set_defaults(defaults);
+        Ok(client)
+    }
+
+    pub fn get_uri(&self) -> Option<&Uri> {
+        self.uri.as_ref()
     }
 
     pub fn uri<T: IntoUri>(mut self, uri: T) -> ClientBuilder {
@@ -76,9 +230,7 @@ impl ClientBuilder {
     }
 
     pub fn headers(mut self, headers: Headers) -> ClientBuilder {
-        for (key, value) in headers.iter() {
-            self.headers.insert(key, &value);
-        }
+        self.headers.merge(&headers);
         self
     }
 
@@ -91,6 +243,16 @@ impl ClientBuilder {
         self
     }
 
+    pub fn header_case(mut self, header_case: HeaderCase) -> ClientBuilder {
+        self.header_case = header_case;
+        self
+    }
+
+    pub fn header_order<T: AsRef<str>>(mut self, order: &[T]) -> ClientBuilder {
+        self.header_order = order.iter().map(|name| name.as_ref().to_string()).collect();
+        self
+    }
+
     pub fn method<T: IntoMethod>(mut self, method: T) -> ClientBuilder {
         if let Ok(method) = method.into_method() {
             self.method = method;
@@ -155,6 +317,59 @@ impl ClientBuilder {
 
     pub fn body(mut self, body: &[u8]) -> ClientBuilder {
         self.body = Some(body.to_vec());
+        self.upload = None;
+        self
+    }
+
+    pub fn body_reader<R: Read + Send + 'static>(mut self, reader: R) -> ClientBuilder {
+        self.upload = Some(Upload::new(reader, None));
+        self.body = None;
+        self
+    }
+
+    pub fn body_reader_with_len<R: Read + Send + 'static>(
+        mut self,
+        reader: R,
+        len: u64,
+    ) -> ClientBuilder {
+        self.upload = Some(Upload::new(reader, Some(len)));
+        self.body = None;
+        self
+    }
+
+    pub fn query<K: AsRef<str>, V: AsRef<str>>(mut self, pairs: &[(K, V)]) -> ClientBuilder {
+        if !pairs.is_empty() {
+            self.query.push(encode_pairs(pairs));
+        }
+        self
+    }
+
+    pub fn form<K: AsRef<str>, V: AsRef<str>>(mut self, pairs: &[(K, V)]) -> ClientBuilder {
+        self.headers
+            .insert("Content-Type", "application/x-www-form-urlencoded");
+        self.body = Some(encode_pairs(pairs).into_bytes());
+        self.upload = None;
+        self
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> ClientBuilder {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.headers.insert("Content-Type", "application/json");
+                self.body = Some(body);
+                self.upload = None;
+            }
+            Err(e) => self.error = Some(Error::JsonEncode(e)),
+        }
+        self
+    }
+
+    pub fn multipart(mut self, form: Form) -> ClientBuilder {
+        self.headers.insert("Content-Type", &form.content_type());
+        let len = form.content_len();
+        self.upload = Some(Upload::new(form.reader(), len));
+        self.body = None;
         self
     }
 
@@ -168,6 +383,105 @@ impl ClientBuilder {
         self
     }
 
+    pub fn redirect(mut self, policy: RedirectPolicy) -> ClientBuilder {
+        self.redirect = policy;
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> ClientBuilder {
+        self.retry = policy;
+        self
+    }
+
+    // Shares the limiter with every client it is given to.
+    pub fn rate_limit(mut self, limiter: &RateLimiter) -> ClientBuilder {
+        self.rate_limit = Some(limiter.clone());
+        self
+    }
+
+    pub fn cookie_jar(mut self, jar: &CookieJar) -> ClientBuilder {
+        self.cookie_jar = Some(jar.clone());
+        self
+    }
+
+    pub fn basic_auth(mut self, username: &str, password: &str) -> ClientBuilder {
+        self.auth = Some(Auth::basic(username, password));
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: &str) -> ClientBuilder {
+        self.auth = Some(Auth::bearer(token));
+        self
+    }
+
+    // Sends the request without credentials and answers the Digest challenge
+    // of a 401 response.
+    pub fn digest_auth(mut self, username: &str, password: &str) -> ClientBuilder {
+        self.auth = Some(Auth::digest(username, password));
+        self
+    }
+
+    pub fn cache(mut self, cache: &Cache) -> ClientBuilder {
+        self.cache = Some(cache.clone());
+        self
+    }
+
+    pub fn decompress(mut self, enable: bool) -> ClientBuilder {
+        self.decompress = enable;
+        self
+    }
+
+    pub fn sniff_charset(mut self, enable: bool) -> ClientBuilder {
+        self.sniff_charset = enable;
+        self
+    }
+
+    pub fn expect_continue(mut self, timeout: Duration) -> ClientBuilder {
+        self.expect_continue = Some(timeout);
+        self
+    }
+
+    pub fn on_info<F: Fn(&Response) + Send + Sync + 'static>(mut self, f: F) -> ClientBuilder {
+        self.on_info = Some(Callback::new(f));
+        self
+    }
+
+    pub fn on_download_progress<F: Fn(&Progress) + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> ClientBuilder {
+        self.on_download = Some(Callback::new(f));
+        self
+    }
+
+    pub fn on_upload_progress<F: Fn(&Progress) + Send + Sync + 'static>(
+        mut self,
+        f: F,
+    ) -> ClientBuilder {
+        self.on_upload = Some(Callback::new(f));
+        self
+    }
+
+    pub fn max_status_line(mut self, len: usize) -> ClientBuilder {
+        self.limits.status_line = len;
+        self
+    }
+
+    pub fn max_headers(mut self, count: usize) -> ClientBuilder {
+        self.limits.header_count = count;
+        self
+    }
+
+    pub fn max_header_bytes(mut self, len: usize) -> ClientBuilder {
+        self.limits.header_bytes = len;
+        self
+    }
+
+    pub fn max_body_bytes(mut self, len: u64) -> ClientBuilder {
+        self.limits.body_bytes = Some(len);
+        self
+    }
+
     // pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
     //     self.timeout = Some(timeout);
     //     self
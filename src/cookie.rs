@@ -0,0 +1,499 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::date::parse_http_date;
+use crate::error::{Error, Result};
+use crate::uri::Uri;
+
+// 9999-12-31T23:59:59Z.
+const MAX_EXPIRY_SECS: u64 = 253_402_300_799;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl FromStr for SameSite {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SameSite> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(Error::ParseCookie),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    creation: SystemTime,
+}
+
+impl Cookie {
+    pub fn parse(set_cookie: &str, uri: &Uri) -> Result<Cookie> {
+        let mut parts = set_cookie.split(';');
+        let pair = parts.next().ok_or(Error::ParseCookie)?;
+        let idx = pair.find('=').ok_or(Error::ParseCookie)?;
+        let name = pair[..idx].trim();
+        if name.is_empty() {
+            return Err(Error::ParseCookie);
+        }
+        let value = pair[idx + 1..].trim();
+
+        let host = uri.host().to_ascii_lowercase();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(uri.path()),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            creation: SystemTime::now(),
+        };
+
+        let mut max_age = None;
+        for attr in parts {
+            let (key, val) = match attr.find('=') {
+                Some(i) => (attr[..i].trim(), attr[i + 1..].trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Some(time) = parse_http_date(val) {
+                        cookie.expires = Some(time);
+                    }
+                }
+                "max-age" => {
+                    if let Ok(secs) = val.parse::<i64>() {
+                        max_age = Some(secs);
+                    }
+                }
+                "domain" => {
+                    let domain = val.trim_start_matches('.').to_ascii_lowercase();
+                    // There is no public suffix list, so only single labels
+                    // such as `com` are refused; `co.uk` is still accepted. A
+                    // single label naming the host itself stays host-only.
+                    if domain.is_empty() || (domain == host && !domain.contains('.')) {
+                        continue;
+                    }
+                    if !domain.contains('.') || !domain_match(&host, &domain) {
+                        return Err(Error::CookieDomain(domain));
+                    }
+                    cookie.host_only = false;
+                    cookie.domain = domain;
+                }
+                "path" if val.starts_with('/') => cookie.path = val.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => cookie.same_site = val.parse().ok(),
+                _ => (),
+            }
+        }
+
+        if let Some(secs) = max_age {
+            cookie.expires = if secs <= 0 {
+                Some(UNIX_EPOCH)
+            } else {
+                let age = Duration::from_secs(secs as u64);
+                Some(
+                    SystemTime::now()
+                        .checked_add(age)
+                        .unwrap_or_else(max_expiry),
+                )
+            };
+        }
+
+        Ok(cookie)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn host_only(&self) -> bool {
+        self.host_only
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match self.expires {
+            Some(time) => time <= SystemTime::now(),
+            None => false,
+        }
+    }
+
+    pub fn matches(&self, uri: &Uri) -> bool {
+        let host = uri.host().to_ascii_lowercase();
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain
+            && path_match(uri.path().unwrap_or("/"), &self.path)
+            && (!self.secure || uri.is_ssl())
+            && !self.is_expired()
+    }
+
+    fn same_key(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn to_netscape(&self) -> String {
+        let domain = if self.host_only {
+            self.domain.clone()
+        } else {
+            format!(".{}", self.domain)
+        };
+        let expires = self
+            .expires
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { "#HttpOnly_" } else { "" },
+            domain,
+            bool_str(!self.host_only),
+            self.path,
+            bool_str(self.secure),
+            expires,
+            self.name,
+            self.value
+        )
+    }
+
+    fn from_netscape(line: &str) -> Result<Cookie> {
+        let (http_only, line) = if let Some(line) = line.strip_prefix("#HttpOnly_") {
+            (true, line)
+        } else {
+            (false, line)
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            return Err(Error::ParseCookie);
+        }
+        let expires: u64 = fields[4].parse()?;
+        Ok(Cookie {
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+            domain: fields[0].trim_start_matches('.').to_ascii_lowercase(),
+            host_only: !fields[1].eq_ignore_ascii_case("TRUE"),
+            path: fields[2].to_string(),
+            expires: if expires == 0 {
+                None
+            } else {
+                Some(
+                    UNIX_EPOCH
+                        .checked_add(Duration::from_secs(expires))
+                        .unwrap_or_else(max_expiry),
+                )
+            },
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            http_only,
+            same_site: None,
+            creation: SystemTime::now(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar(Arc<Mutex<Vec<Cookie>>>);
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar::default()
+    }
+
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.0.lock().unwrap();
+        let old = cookies.iter().position(|c| c.same_key(&cookie));
+        let mut cookie = cookie;
+        if let Some(i) = old {
+            cookie.creation = cookies.remove(i).creation;
+        }
+        if !cookie.is_expired() {
+            cookies.push(cookie);
+        }
+    }
+
+    pub fn store<T: AsRef<str>>(&self, uri: &Uri, set_cookies: &[T]) {
+        for set_cookie in set_cookies {
+            if let Ok(cookie) = Cookie::parse(set_cookie.as_ref(), uri) {
+                self.insert(cookie);
+            }
+        }
+    }
+
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let mut cookies = self.0.lock().unwrap();
+        cookies.retain(|c| !c.is_expired());
+        cookies.clone()
+    }
+
+    pub fn matches(&self, uri: &Uri) -> Vec<Cookie> {
+        let mut cookies: Vec<Cookie> = self
+            .cookies()
+            .into_iter()
+            .filter(|c| c.matches(uri))
+            .collect();
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.creation.cmp(&b.creation))
+        });
+        cookies
+    }
+
+    pub fn header(&self, uri: &Uri) -> Option<String> {
+        let cookies = self.matches(uri);
+        if cookies.is_empty() {
+            None
+        } else {
+            Some(
+                cookies
+                    .iter()
+                    .map(|c| format!("{}={}", c.name, c.value))
+                    .collect::<Vec<String>>()
+                    .join("; "),
+            )
+        }
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(b"# Netscape HTTP Cookie File\n")?;
+        for cookie in self.cookies() {
+            writeln!(writer, "{}", cookie.to_netscape())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(&self, reader: R) -> Result<()> {
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || (line.starts_with('#') && !line.starts_with("#HttpOnly_"))
+            {
+                continue;
+            }
+            self.insert(Cookie::from_netscape(line)?);
+        }
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_to(&mut File::create(path)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.read_from(File::open(path)?)
+    }
+}
+
+fn default_path(path: Option<&str>) -> String {
+    match path {
+        Some(path) if path.starts_with('/') => match path.rfind('/') {
+            Some(0) | None => "/".to_string(),
+            Some(i) => path[..i].to_string(),
+        },
+        _ => "/".to_string(),
+    }
+}
+
+// Stands in for an expiry too far away to be represented.
+fn max_expiry() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(MAX_EXPIRY_SECS)
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<IpAddr>().is_err())
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+fn bool_str(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cookie_parse() {
+        let cookie = Cookie::parse(
+            "id=a3fWa; Expires=Wed, 21 Oct 2037 07:28:00 GMT; Secure; HttpOnly; SameSite=Lax",
+            &uri("https://example.com/a/b"),
+        )
+        .unwrap();
+        assert_eq!(cookie.name(), "id");
+        assert_eq!(cookie.value(), "a3fWa");
+        assert_eq!(cookie.domain(), "example.com");
+        assert!(cookie.host_only());
+        assert_eq!(cookie.path(), "/a");
+        assert!(cookie.expires().is_some());
+        assert!(cookie.secure());
+        assert!(cookie.http_only());
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+    }
+
+    #[test]
+    fn cookie_foreign_domain() {
+        assert!(Cookie::parse("a=b; Domain=other.com", &uri("http://example.com/")).is_err());
+        assert!(Cookie::parse("a=b; Domain=com", &uri("http://example.com/")).is_err());
+        let cookie = Cookie::parse("a=b; Domain=localhost", &uri("http://localhost/")).unwrap();
+        assert!(cookie.host_only());
+    }
+
+    #[test]
+    fn cookie_max_age() {
+        let cookie = Cookie::parse(
+            "a=b; Max-Age=0; Expires=Wed, 21 Oct 2037 07:28:00 GMT",
+            &uri("http://example.com/"),
+        )
+        .unwrap();
+        assert!(cookie.is_expired());
+
+        let cookie = Cookie::parse(
+            "a=b; Max-Age=9223372036854775807",
+            &uri("http://example.com/"),
+        )
+        .unwrap();
+        assert_eq!(cookie.expires(), Some(max_expiry()));
+        let line = "example.com\tFALSE\t/\tFALSE\t18446744073709551615\ta\tb";
+        assert_eq!(
+            Cookie::from_netscape(line).unwrap().expires(),
+            Some(max_expiry())
+        );
+    }
+
+    #[test]
+    fn jar_header() {
+        let jar = CookieJar::new();
+        jar.store(
+            &uri("http://www.example.com/"),
+            &[
+                "a=1; Domain=example.com",
+                "b=2; Path=/docs",
+                "c=3; Secure",
+                "d=4",
+            ],
+        );
+        assert_eq!(
+            jar.header(&uri("http://www.example.com/docs/x")),
+            Some("b=2; a=1; d=4".to_string())
+        );
+        assert_eq!(
+            jar.header(&uri("http://sub.example.com/")),
+            Some("a=1".to_string())
+        );
+        assert_eq!(
+            jar.header(&uri("https://www.example.com/")),
+            Some("a=1; c=3; d=4".to_string())
+        );
+        assert_eq!(jar.header(&uri("http://example.org/")), None);
+    }
+
+    #[test]
+    fn jar_replace_and_expire() {
+        let jar = CookieJar::new();
+        let target = uri("http://example.com/");
+        jar.store(&target, &["a=1"]);
+        jar.store(&target, &["a=2"]);
+        assert_eq!(jar.header(&target), Some("a=2".to_string()));
+        jar.store(&target, &["a=3; Max-Age=-1"]);
+        assert_eq!(jar.header(&target), None);
+    }
+
+    #[test]
+    fn jar_netscape() {
+        let jar = CookieJar::new();
+        jar.store(
+            &uri("https://www.example.com/"),
+            &[
+                "a=1; Domain=example.com; Secure",
+                "b=2; HttpOnly; Max-Age=3600",
+            ],
+        );
+        let mut buf = Vec::new();
+        jar.write_to(&mut buf).unwrap();
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert!(text.contains(".example.com\tTRUE\t/\tTRUE\t0\ta\t1"));
+        assert!(text.contains("#HttpOnly_www.example.com\tFALSE\t/\tFALSE\t"));
+
+        let loaded = CookieJar::new();
+        loaded.read_from(&buf[..]).unwrap();
+        assert_eq!(
+            loaded.header(&uri("https://www.example.com/")),
+            Some("a=1; b=2".to_string())
+        );
+    }
+}
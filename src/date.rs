@@ -0,0 +1,169 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+// Parses dates with the algorithm from RFC 6265, section 5.1.1. It accepts
+// all three HTTP-date formats as well as the looser forms used in cookies.
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    for token in s.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            if let Some(t) = parse_time(token) {
+                time = Some(t);
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = parse_digits(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() && token.len() >= 3 && token.is_char_boundary(3) {
+            let name = token[..3].to_ascii_lowercase();
+            if let Some(m) = MONTHS.iter().position(|&m| m == name) {
+                month = Some(m as u64 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = parse_digits(token, 2, 4) {
+                year = Some(y);
+                continue;
+            }
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if (70..=99).contains(&year) {
+        year += 1900;
+    } else if year <= 69 {
+        year += 2000;
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    if secs < 0 {
+        Some(UNIX_EPOCH)
+    } else {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
+}
+
+pub fn fmt_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let rem = secs % 86400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        capitalize(MONTHS[month as usize - 1]),
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+fn is_delimiter(c: char) -> bool {
+    matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~')
+}
+
+fn parse_digits(token: &str, min: usize, max: usize) -> Option<u64> {
+    let len = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if len < min || len > max {
+        return None;
+    }
+    token[..len].parse().ok()
+}
+
+fn parse_time(token: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = token.splitn(3, ':');
+    let hour = parse_digits(parts.next()?, 1, 2)?;
+    let minute = parse_digits(parts.next()?, 1, 2)?;
+    let second = parse_digits(parts.next()?, 1, 2)?;
+    Some((hour, minute, second))
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: u64 = 784_111_777;
+
+    #[test]
+    fn date_rfc1123() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(date, UNIX_EPOCH + Duration::from_secs(TIME));
+    }
+
+    #[test]
+    fn date_rfc850() {
+        let date = parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").unwrap();
+        assert_eq!(date, UNIX_EPOCH + Duration::from_secs(TIME));
+    }
+
+    #[test]
+    fn date_asctime() {
+        let date = parse_http_date("Sun Nov  6 08:49:37 1994").unwrap();
+        assert_eq!(date, UNIX_EPOCH + Duration::from_secs(TIME));
+    }
+
+    #[test]
+    fn date_invalid() {
+        assert!(parse_http_date("Sun, 06 Nov 1994").is_none());
+        assert!(parse_http_date("Sun, 32 Nov 1994 08:49:37 GMT").is_none());
+    }
+
+    #[test]
+    fn date_fmt() {
+        let date = UNIX_EPOCH + Duration::from_secs(TIME);
+        assert_eq!(fmt_http_date(date), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+}
@@ -0,0 +1,620 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+
+use sha2::{Digest, Sha256};
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::hash::to_hex;
+use crate::headers::Headers;
+use crate::method::Method;
+use crate::response::Response;
+use crate::uri::{IntoUri, Uri};
+
+#[derive(Debug)]
+pub struct Download {
+    client: Client,
+    retries: usize,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    written: u64,
+    total: Option<u64>,
+    validator: Option<String>,
+}
+
+impl Download {
+    pub fn new(mut client: Client) -> Download {
+        // Ranges apply to the encoded bytes, so ask for the identity encoding.
+        client.request_mut().header("Accept-Encoding", "identity");
+        client.decompress(false);
+        Download { client, retries: 3 }
+    }
+
+    pub fn retries(mut self, retries: usize) -> Download {
+        self.retries = retries;
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn to_file<P: AsRef<Path>>(mut self, path: P) -> Result<u64> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        let mut state = State::default();
+        let mut attempt = 0;
+        loop {
+            let result = if attempt == 0 {
+                self.attempt(&mut file, &mut state)
+            } else {
+                self.client
+                    .reconnect()
+                    .and_then(|_| self.attempt(&mut file, &mut state))
+            };
+            match result {
+                Ok(()) => return Ok(state.written),
+                Err(Error::Io(_)) | Err(Error::ParseChunk) if attempt < self.retries => {
+                    attempt += 1
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn attempt(&mut self, file: &mut File, state: &mut State) -> Result<()> {
+        let request = self.client.request_mut();
+        if state.written > 0 {
+            request.header("Range", &format!("bytes={}-", state.written));
+            match &state.validator {
+                Some(validator) => request.header("If-Range", validator),
+                None => request.remove_header("If-Range"),
+            };
+        } else {
+            request.remove_header("Range");
+            request.remove_header("If-Range");
+        }
+
+        let response = self.client.send()?;
+        match u16::from(response.status_code()) {
+            206 if state.written > 0 => {
+                let range = response
+                    .headers()
+                    .get("Content-Range")
+                    .ok_or(Error::ParseContentRange)?;
+                let (start, _, total) =
+                    parse_content_range(&range).ok_or(Error::ParseContentRange)?;
+                if start != state.written {
+                    return Err(Error::ParseContentRange);
+                }
+                state.total = total.or(state.total);
+            }
+            // The server ignored the range or the file changed, start over.
+            200 => {
+                file.set_len(0)?;
+                state.written = 0;
+                state.total = response
+                    .headers()
+                    .get("Content-Length")
+                    .and_then(|len| len.trim().parse().ok());
+                state.validator = validator(&response);
+            }
+            _ => return Err(Error::UnexpectedStatus(response.status_code())),
+        }
+
+        file.seek(SeekFrom::Start(state.written))?;
+        let mut reader = self.client.body_reader()?;
+        let mut buf = vec![0u8; 16 * 1024];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buf[..read])?;
+            state.written += read as u64;
+        }
+        file.flush()?;
+        match state.total {
+            Some(total) if state.written < total => {
+                Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SegmentedDownload {
+    uri: Uri,
+    proxies: Vec<Uri>,
+    headers: Headers,
+    segments: usize,
+    retries: usize,
+    sha256: Option<String>,
+}
+
+impl SegmentedDownload {
+    pub fn new<U: IntoUri>(uri: U) -> Result<SegmentedDownload> {
+        Ok(SegmentedDownload {
+            uri: uri.into_uri()?,
+            proxies: Vec::new(),
+            headers: Headers::new(),
+            segments: 4,
+            retries: 3,
+            sha256: None,
+        })
+    }
+
+    pub fn proxy<T: IntoUri>(mut self, proxy: T) -> SegmentedDownload {
+        if let Ok(proxy) = proxy.into_uri() {
+            self.proxies.push(proxy);
+        }
+        self
+    }
+
+    pub fn header<T: ToString + ?Sized, U: ToString + ?Sized>(
+        mut self,
+        key: &T,
+        value: &U,
+    ) -> SegmentedDownload {
+        self.headers.insert(key, value);
+        self
+    }
+
+    pub fn segments(mut self, segments: usize) -> SegmentedDownload {
+        self.segments = segments.max(1);
+        self
+    }
+
+    pub fn retries(mut self, retries: usize) -> SegmentedDownload {
+        self.retries = retries;
+        self
+    }
+
+    // The SHA-256 of the whole file, in hex, checked once it is assembled.
+    pub fn sha256(mut self, hex: &str) -> SegmentedDownload {
+        self.sha256 = Some(hex.trim().to_ascii_lowercase());
+        self
+    }
+
+    // Segment `i` starts on proxy `i` and moves to the next proxy on every retry.
+    fn proxy_for(&self, segment: usize, attempt: usize) -> Option<&Uri> {
+        if self.proxies.is_empty() {
+            None
+        } else {
+            Some(&self.proxies[(segment + attempt) % self.proxies.len()])
+        }
+    }
+
+    fn client(&self, proxy: Option<&Uri>) -> Result<Client> {
+        let mut builder = Client::new(self.uri.clone())
+            .headers(self.headers.clone())
+            .header("Accept-Encoding", "identity")
+            .decompress(false);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        builder.build()
+    }
+
+    // Each segment is checked against the Content-MD5 the server sends for
+    // it, if any, and fetched again on another proxy when it doesn't match.
+    pub fn to_file<P: AsRef<Path>>(self, path: P) -> Result<u64> {
+        let path = path.as_ref();
+        let len = self.download(path)?;
+        if let Some(expected) = &self.sha256 {
+            let mut hasher = Sha256::new();
+            io::copy(&mut File::open(path)?, &mut hasher)?;
+            if to_hex(&hasher.finalize()) != *expected {
+                return Err(Error::ChecksumMismatch);
+            }
+        }
+        Ok(len)
+    }
+
+    fn download(&self, path: &Path) -> Result<u64> {
+        let (total, validator) = match self.probe()? {
+            Some(probe) => probe,
+            None => {
+                let client = self.client(self.proxy_for(0, 0))?;
+                return Download::new(client).retries(self.retries).to_file(path);
+            }
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(total)?;
+        if total == 0 {
+            return Ok(0);
+        }
+
+        let size = total.div_ceil(self.segments as u64);
+        let ranges: Vec<(u64, u64)> = (0..total)
+            .step_by(size as usize)
+            .map(|first| (first, (first + size).min(total) - 1))
+            .collect();
+        let results: Vec<Result<()>> = thread::scope(|scope| {
+            let handles: Vec<_> = ranges
+                .iter()
+                .enumerate()
+                .map(|(segment, &range)| {
+                    let validator = validator.as_deref();
+                    scope.spawn(move || self.segment(path, segment, range, validator))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(Error::UnknownError)))
+                .collect()
+        });
+        for result in results {
+            result?;
+        }
+        Ok(total)
+    }
+
+    // Returns the size and validator if the server accepts byte ranges.
+    fn probe(&self) -> Result<Option<(u64, Option<String>)>> {
+        let mut attempt = 0;
+        let response = loop {
+            let result = self
+                .client(self.proxy_for(0, attempt))
+                .and_then(|mut client| {
+                    client.request_mut().method(Method::HEAD);
+                    client.send()
+                });
+            match result {
+                Ok(response) => break response,
+                Err(_) if attempt < self.retries => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        if !response.status_code().is_success() {
+            return Err(Error::UnexpectedStatus(response.status_code()));
+        }
+        let ranges = response
+            .headers()
+            .get("Accept-Ranges")
+            .is_some_and(|v| v.split(',').any(|v| v.trim().eq_ignore_ascii_case("bytes")));
+        let len = response
+            .headers()
+            .get("Content-Length")
+            .and_then(|len| len.trim().parse().ok());
+        match len {
+            Some(len) if ranges => Ok(Some((len, validator(&response)))),
+            _ => Ok(None),
+        }
+    }
+
+    fn segment(
+        &self,
+        path: &Path,
+        segment: usize,
+        range: (u64, u64),
+        validator: Option<&str>,
+    ) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.fetch(path, self.proxy_for(segment, attempt), range, validator) {
+                Ok(()) => return Ok(()),
+                Err(_) if attempt < self.retries => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn fetch(
+        &self,
+        path: &Path,
+        proxy: Option<&Uri>,
+        (first, last): (u64, u64),
+        validator: Option<&str>,
+    ) -> Result<()> {
+        let mut client = self.client(proxy)?;
+        let request = client.request_mut();
+        request.header("Range", &format!("bytes={}-{}", first, last));
+        if let Some(validator) = validator {
+            request.header("If-Range", validator);
+        }
+        let response = client.send()?;
+        if u16::from(response.status_code()) != 206 {
+            return Err(Error::UnexpectedStatus(response.status_code()));
+        }
+        match response
+            .headers()
+            .get("Content-Range")
+            .and_then(|range| parse_content_range(&range))
+        {
+            Some((f, l, _)) if f == first && l == last => (),
+            _ => return Err(Error::ParseContentRange),
+        }
+
+        let len = last - first + 1;
+        let mut check = SegmentCheck::new(&response);
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(first))?;
+        let mut body = client.body_reader()?.take(len);
+        let mut buf = vec![0u8; 16 * 1024];
+        let mut written = 0;
+        loop {
+            let read = body.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buf[..read])?;
+            check.update(&buf[..read]);
+            written += read as u64;
+        }
+        if written != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        file.flush()?;
+        check.finish()
+    }
+}
+
+// Compares a segment with its Content-MD5, which covers just the bytes of a
+// 206 response. Without the md5 feature the header is ignored.
+struct SegmentCheck {
+    #[cfg(feature = "md5")]
+    md5: Option<(Vec<u8>, md5::Md5)>,
+}
+
+impl SegmentCheck {
+    #[allow(unused_variables)]
+    fn new(response: &Response) -> SegmentCheck {
+        SegmentCheck {
+            #[cfg(feature = "md5")]
+            md5: response
+                .headers()
+                .get("Content-MD5")
+                .and_then(|value| base64::decode(value.trim()).ok())
+                .map(|expected| (expected, md5::Md5::new())),
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn update(&mut self, data: &[u8]) {
+        #[cfg(feature = "md5")]
+        if let Some((_, md5)) = &mut self.md5 {
+            md5.update(data);
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        #[cfg(feature = "md5")]
+        if let Some((expected, md5)) = self.md5 {
+            if md5.finalize()[..] != expected[..] {
+                return Err(Error::ChecksumMismatch);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Only strong validators may be used with If-Range.
+fn validator(response: &Response) -> Option<String> {
+    match response.headers().get("ETag") {
+        Some(etag) if !etag.starts_with("W/") => Some(etag),
+        _ => response.headers().get("Last-Modified"),
+    }
+}
+
+// Parses `bytes first-last/complete`, where complete may be `*`.
+pub fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let value = value.trim();
+    if !value.get(..6)?.eq_ignore_ascii_case("bytes ") {
+        return None;
+    }
+    let (range, total) = value[6..].trim().split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let first = first.trim().parse().ok()?;
+    let last = last.trim().parse().ok()?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    if last < first || total.is_some_and(|total| last >= total) {
+        return None;
+    }
+    Some((first, last, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn download_content_range() {
+        assert_eq!(parse_content_range("bytes 5-9/10"), Some((5, 9, Some(10))));
+        assert_eq!(parse_content_range("bytes 0-0/*"), Some((0, 0, None)));
+        assert_eq!(parse_content_range("bytes 5-9/9"), None);
+        assert_eq!(parse_content_range("items 5-9/10"), None);
+    }
+
+    #[test]
+    fn download_resume() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 10\r\n\r\n01234".to_vec(),
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-9/10\r\n\
+              Content-Length: 5\r\n\r\n56789"
+                .to_vec(),
+        ]);
+        let path = env::temp_dir().join(format!("rp_download_{}", crate::random::random_u64()));
+        let client = Client::new(uri.as_str()).build().unwrap();
+        let len = Download::new(client).to_file(&path).unwrap();
+        assert_eq!(len, 10);
+        assert_eq!(fs::read(&path).unwrap(), b"0123456789");
+        fs::remove_file(&path).unwrap();
+        rx.recv().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("Range: bytes=5-\r\n"));
+        assert!(request.contains("If-Range: \"v1\"\r\n"));
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    #[cfg_attr(not(feature = "md5"), allow(dead_code))]
+    enum Serve {
+        Good,
+        // Drops every connection.
+        Broken,
+        // Sends upper case bytes with the Content-MD5 of the right ones.
+        Corrupt,
+    }
+
+    // Serves CONTENT honouring Range, with the Content-MD5 of each range.
+    fn range_server(serve: Serve) -> String {
+        use std::net::TcpListener;
+
+        const CONTENT: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut head = Vec::new();
+                    let mut byte = [0u8; 1];
+                    while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                        head.push(byte[0]);
+                    }
+                    if serve == Serve::Broken {
+                        return;
+                    }
+                    let head = String::from_utf8(head).unwrap();
+                    let range = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Range: bytes="))
+                        .and_then(|range| range.split_once('-'))
+                        .map(|(f, l)| (f.parse::<usize>().unwrap(), l.parse::<usize>().unwrap()));
+                    let response = match range {
+                        _ if head.starts_with("HEAD ") => format!(
+                            "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n",
+                            CONTENT.len()
+                        )
+                        .into_bytes(),
+                        Some((first, last)) => {
+                            let part = &CONTENT[first..=last];
+                            #[cfg(feature = "md5")]
+                            let md5 = format!(
+                                "Content-MD5: {}\r\n",
+                                base64::encode(&crate::hash::md5(part))
+                            );
+                            #[cfg(not(feature = "md5"))]
+                            let md5 = String::new();
+                            let mut response = format!(
+                                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n\
+                                 Content-Length: {}\r\n{}\r\n",
+                                first,
+                                last,
+                                CONTENT.len(),
+                                part.len(),
+                                md5
+                            )
+                            .into_bytes();
+                            match serve {
+                                Serve::Corrupt => response.extend(part.to_ascii_uppercase()),
+                                _ => response.extend(part),
+                            }
+                            response
+                        }
+                        None => Vec::new(),
+                    };
+                    stream.write_all(&response).unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn download_segmented() {
+        let good = range_server(Serve::Good);
+        let bad = range_server(Serve::Broken);
+        let path = env::temp_dir().join(format!("rp_download_{}", crate::random::random_u64()));
+        let len = SegmentedDownload::new("http://files.example/alphabet")
+            .unwrap()
+            .proxy(good.as_str())
+            .proxy(bad.as_str())
+            .segments(5)
+            .to_file(&path)
+            .unwrap();
+        assert_eq!(len, 26);
+        assert_eq!(fs::read(&path).unwrap(), b"abcdefghijklmnopqrstuvwxyz");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn download_checksum() {
+        let good = range_server(Serve::Good);
+        let path = env::temp_dir().join(format!("rp_download_{}", crate::random::random_u64()));
+        let download = || {
+            SegmentedDownload::new("http://files.example/alphabet")
+                .unwrap()
+                .proxy(good.as_str())
+                .segments(3)
+        };
+        let alphabet = "71c480df93d6ae2f1efad1447c66c9525e316218cf51fc8d9ed832f2daf18b73";
+        assert_eq!(download().sha256(alphabet).to_file(&path).unwrap(), 26);
+        assert!(matches!(
+            download()
+                .sha256(&alphabet.replace('7', "8"))
+                .to_file(&path),
+            Err(Error::ChecksumMismatch)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "md5")]
+    #[test]
+    fn download_segment_md5() {
+        let corrupt = range_server(Serve::Corrupt);
+        let good = range_server(Serve::Good);
+        let path = env::temp_dir().join(format!("rp_download_{}", crate::random::random_u64()));
+        let download = SegmentedDownload::new("http://files.example/alphabet")
+            .unwrap()
+            .proxy(corrupt.as_str())
+            .segments(2);
+        assert!(matches!(
+            download.retries(0).to_file(&path),
+            Err(Error::ChecksumMismatch)
+        ));
+        // Every segment is retried on the good proxy.
+        let len = SegmentedDownload::new("http://files.example/alphabet")
+            .unwrap()
+            .proxy(good.as_str())
+            .proxy(corrupt.as_str())
+            .segments(2)
+            .to_file(&path)
+            .unwrap();
+        assert_eq!(len, 26);
+        assert_eq!(fs::read(&path).unwrap(), b"abcdefghijklmnopqrstuvwxyz");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn download_restart() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nxxxxx".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789".to_vec(),
+        ]);
+        let path = env::temp_dir().join(format!("rp_download_{}", crate::random::random_u64()));
+        let client = Client::new(uri.as_str()).build().unwrap();
+        let len = Download::new(client).to_file(&path).unwrap();
+        assert_eq!(len, 10);
+        assert_eq!(fs::read(&path).unwrap(), b"0123456789");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::Read;
+
+use crate::error::{Error, Result};
+
+pub type Decoder = Box<dyn Read + Send>;
+
+pub fn accept_encoding() -> Option<String> {
+    let mut encodings: Vec<&str> = Vec::new();
+    if cfg!(feature = "gzip") {
+        encodings.push("gzip");
+    }
+    if cfg!(feature = "deflate") {
+        encodings.push("deflate");
+    }
+    if cfg!(feature = "brotli") {
+        encodings.push("br");
+    }
+    if encodings.is_empty() {
+        None
+    } else {
+        Some(encodings.join(", "))
+    }
+}
+
+// Encodings are removed last applied first. A body with an encoding whose
+// codec isn't compiled in is returned as sent, as Content-Encoding describes.
+pub fn decoder(reader: Decoder, content_encoding: Option<&str>) -> Result<Decoder> {
+    let encodings: Vec<String> = match content_encoding {
+        Some(value) => value
+            .rsplit(',')
+            .map(|encoding| encoding.trim().to_ascii_lowercase())
+            .collect(),
+        None => Vec::new(),
+    };
+    if !encodings.iter().all(|encoding| is_supported(encoding)) {
+        return Ok(reader);
+    }
+    let mut reader = reader;
+    for encoding in &encodings {
+        reader = decode(reader, encoding)?;
+    }
+    Ok(reader)
+}
+
+fn is_supported(encoding: &str) -> bool {
+    matches!(encoding, "" | "identity")
+        || (cfg!(feature = "gzip") && matches!(encoding, "gzip" | "x-gzip"))
+        || (cfg!(feature = "deflate") && encoding == "deflate")
+        || (cfg!(feature = "brotli") && encoding == "br")
+}
+
+fn decode(reader: Decoder, encoding: &str) -> Result<Decoder> {
+    match encoding {
+        "" | "identity" => Ok(reader),
+        #[cfg(feature = "gzip")]
+        "gzip" | "x-gzip" => Ok(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "deflate")]
+        "deflate" => Ok(Box::new(flate2::read::ZlibDecoder::new(reader))),
+        #[cfg(feature = "brotli")]
+        "br" => Ok(Box::new(brotli_decompressor::Decompressor::new(
+            reader, 4096,
+        ))),
+        e => Err(Error::UnsupportedEncoding(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(body: Vec<u8>, content_encoding: &str) -> Result<Vec<u8>> {
+        let mut reader = decoder(Box::new(std::io::Cursor::new(body)), Some(content_encoding))?;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn identity() {
+        assert_eq!(decode_all(b"hello".to_vec(), "identity").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn unsupported() {
+        assert_eq!(decode_all(b"hello".to_vec(), "compress").unwrap(), b"hello");
+        assert_eq!(
+            decode_all(b"hello".to_vec(), "compress, identity").unwrap(),
+            b"hello"
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(decode_all(body, "gzip").unwrap(), b"hello");
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn deflate() {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(decode_all(body, "deflate").unwrap(), b"hello");
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn brotli() {
+        let body = vec![0x0b, 0x02, 0x80, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x03];
+        assert_eq!(decode_all(body, "br").unwrap(), b"hello");
+    }
+
+    #[cfg(all(feature = "gzip", feature = "deflate"))]
+    #[test]
+    fn stacked() {
+        use flate2::{write::GzEncoder, write::ZlibEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let body = encoder.finish().unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let body = encoder.finish().unwrap();
+        assert_eq!(decode_all(body, "deflate, gzip").unwrap(), b"hello");
+    }
+}
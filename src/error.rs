@@ -46,6 +46,39 @@ pub enum Error {
     HeadersErr,
     ParseInt(std::num::ParseIntError),
     Utf8(std::str::Utf8Error),
+    UnsupportedEncoding(String),
+    ParseChunk,
+    TooManyRedirects(usize),
+    RedirectLoop(String),
+    ParseCookie,
+    CookieDomain(String),
+    #[cfg(feature = "json")]
+    JsonDecode(crate::status::StatusCode, serde_json::Error),
+    InvalidHeaderName(String),
+    InvalidHeaderValue(String),
+    InvalidRequestTarget(String),
+    StatusLineTooLong(usize),
+    TooManyHeaders(usize),
+    HeadersTooLarge(usize),
+    BodyTooLarge(u64),
+    UnexpectedStatus(crate::status::StatusCode),
+    ParseContentRange,
+    ParseCache,
+    WebSocketHandshake(&'static str),
+    WebSocketProtocol(&'static str),
+    WebSocketClosed,
+    FrameTooLarge(usize),
+    MessageTooLarge(usize),
+    UnexpectedContentType(String),
+    UnsupportedCharset(String),
+    RateLimited(std::time::Duration),
+    #[cfg(feature = "json")]
+    JsonEncode(serde_json::Error),
+    ChecksumMismatch,
+    LineTooLong(usize),
+    #[cfg(feature = "tokio")]
+    UnsupportedAsync(&'static str),
+    JobPanicked,
 }
 
 impl fmt::Display for Error {
@@ -95,11 +128,45 @@ impl fmt::Display for Error {
             HeadersErr => write!(w, "bad headers"),
             ParseInt(e) => write!(w, "{}", e),
             Utf8(e) => write!(w, "{}", e),
+            UnsupportedEncoding(e) => write!(w, "unsupported content encoding {}", e),
+            ParseChunk => write!(w, "parse chunk"),
+            TooManyRedirects(e) => write!(w, "too many redirects, limit {}", e),
+            RedirectLoop(e) => write!(w, "redirect loop detected at {}", e),
+            ParseCookie => write!(w, "parse cookie"),
+            CookieDomain(e) => write!(w, "cookie domain {} does not match host", e),
+            #[cfg(feature = "json")]
+            JsonDecode(status, e) => write!(w, "json decode error, status {}: {}", status, e),
+            InvalidHeaderName(e) => write!(w, "invalid header name: {:?}", e),
+            InvalidHeaderValue(e) => write!(w, "invalid value for header {}", e),
+            InvalidRequestTarget(e) => write!(w, "invalid request target: {:?}", e),
+            StatusLineTooLong(e) => write!(w, "status line longer than {} bytes", e),
+            TooManyHeaders(e) => write!(w, "more than {} headers", e),
+            HeadersTooLarge(e) => write!(w, "headers larger than {} bytes", e),
+            BodyTooLarge(e) => write!(w, "body larger than {} bytes", e),
+            UnexpectedStatus(e) => write!(w, "unexpected status code {}", e),
+            ParseContentRange => write!(w, "invalid Content-Range"),
+            ParseCache => write!(w, "invalid cache entry"),
+            WebSocketHandshake(e) => write!(w, "websocket handshake failed: {}", e),
+            WebSocketProtocol(e) => write!(w, "websocket protocol error: {}", e),
+            WebSocketClosed => write!(w, "websocket closed"),
+            FrameTooLarge(e) => write!(w, "websocket frame larger than {} bytes", e),
+            MessageTooLarge(e) => write!(w, "websocket message larger than {} bytes", e),
+            UnexpectedContentType(e) => write!(w, "unexpected content type {}", e),
+            UnsupportedCharset(e) => write!(w, "unsupported charset {}", e),
+            RateLimited(e) => write!(w, "rate limit reached, next token in {:?}", e),
+            #[cfg(feature = "json")]
+            JsonEncode(e) => write!(w, "json encode error: {}", e),
+            ChecksumMismatch => write!(w, "checksum mismatch"),
+            LineTooLong(e) => write!(w, "line longer than {} bytes", e),
+            #[cfg(feature = "tokio")]
+            UnsupportedAsync(e) => write!(w, "{} is not supported by the async client", e),
+            JobPanicked => write!(w, "batch job panicked"),
         }
     }
 }
 
 impl error::Error for Error {
+    #[allow(deprecated)]
     fn description(&self) -> &str {
         use self::Error::*;
 
@@ -146,6 +213,39 @@ impl error::Error for Error {
             HeadersErr => "bad headers",
             ParseInt(e) => e.description(),
             Utf8(e) => e.description(),
+            UnsupportedEncoding(_) => "unsupported content encoding",
+            ParseChunk => "parse chunk",
+            TooManyRedirects(_) => "too many redirects",
+            RedirectLoop(_) => "redirect loop detected",
+            ParseCookie => "parse cookie",
+            CookieDomain(_) => "cookie domain does not match host",
+            #[cfg(feature = "json")]
+            JsonDecode(_, e) => e.description(),
+            InvalidHeaderName(_) => "invalid header name",
+            InvalidHeaderValue(_) => "invalid header value",
+            InvalidRequestTarget(_) => "invalid request target",
+            StatusLineTooLong(_) => "status line too long",
+            TooManyHeaders(_) => "too many headers",
+            HeadersTooLarge(_) => "headers too large",
+            BodyTooLarge(_) => "body too large",
+            UnexpectedStatus(_) => "unexpected status code",
+            ParseContentRange => "invalid Content-Range",
+            ParseCache => "invalid cache entry",
+            WebSocketHandshake(_) => "websocket handshake failed",
+            WebSocketProtocol(_) => "websocket protocol error",
+            WebSocketClosed => "websocket closed",
+            FrameTooLarge(_) => "websocket frame too large",
+            MessageTooLarge(_) => "websocket message too large",
+            UnexpectedContentType(_) => "unexpected content type",
+            UnsupportedCharset(_) => "unsupported charset",
+            RateLimited(_) => "rate limit reached",
+            #[cfg(feature = "json")]
+            JsonEncode(e) => e.description(),
+            ChecksumMismatch => "checksum mismatch",
+            LineTooLong(_) => "line too long",
+            #[cfg(feature = "tokio")]
+            UnsupportedAsync(_) => "setting not supported by the async client",
+            JobPanicked => "batch job panicked",
         }
     }
 
@@ -195,13 +295,49 @@ impl error::Error for Error {
             HeadersErr => None,
             ParseInt(e) => e.source(),
             Utf8(e) => e.source(),
+            UnsupportedEncoding(_) => None,
+            ParseChunk => None,
+            TooManyRedirects(_) => None,
+            RedirectLoop(_) => None,
+            ParseCookie => None,
+            CookieDomain(_) => None,
+            #[cfg(feature = "json")]
+            JsonDecode(_, e) => e.source(),
+            InvalidHeaderName(_) => None,
+            InvalidHeaderValue(_) => None,
+            InvalidRequestTarget(_) => None,
+            StatusLineTooLong(_) => None,
+            TooManyHeaders(_) => None,
+            HeadersTooLarge(_) => None,
+            BodyTooLarge(_) => None,
+            UnexpectedStatus(_) => None,
+            ParseContentRange => None,
+            ParseCache => None,
+            WebSocketHandshake(_) => None,
+            WebSocketProtocol(_) => None,
+            WebSocketClosed => None,
+            FrameTooLarge(_) => None,
+            MessageTooLarge(_) => None,
+            UnexpectedContentType(_) => None,
+            UnsupportedCharset(_) => None,
+            RateLimited(_) => None,
+            #[cfg(feature = "json")]
+            JsonEncode(e) => e.source(),
+            ChecksumMismatch => None,
+            LineTooLong(_) => None,
+            #[cfg(feature = "tokio")]
+            UnsupportedAsync(_) => None,
+            JobPanicked => None,
         }
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error::Io(err)
+        match err.get_ref().map(|e| e.is::<Error>()) {
+            Some(true) => *err.into_inner().unwrap().downcast::<Error>().unwrap(),
+            _ => Error::Io(err),
+        }
     }
 }
 
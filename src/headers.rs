@@ -1,39 +1,183 @@
-use std::collections::{hash_map, HashMap};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::mem;
+use std::slice;
 use std::str::FromStr;
 
 use crate::error::{Error, Result};
 
+// tchar from RFC 7230, section 3.2.6.
+pub fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+// field-value from RFC 7230, section 3.2, without the obsolete line folding.
+pub fn is_field_value(s: &str) -> bool {
+    s.bytes().all(|b| b == b'\t' || (b >= 0x20 && b != 0x7f))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum HeaderCase {
+    #[default]
+    Preserve,
+    Title,
+    Lower,
+}
+
+impl HeaderCase {
+    pub fn apply(self, name: &str) -> String {
+        match self {
+            HeaderCase::Preserve => name.to_string(),
+            HeaderCase::Lower => name.to_ascii_lowercase(),
+            HeaderCase::Title => name
+                .split('-')
+                .map(|part| {
+                    let mut part = part.to_ascii_lowercase();
+                    if let Some(first) = part.get_mut(..1) {
+                        first.make_ascii_uppercase();
+                    }
+                    part
+                })
+                .collect::<Vec<String>>()
+                .join("-"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default)]
-pub struct Headers(HashMap<String, String>);
+pub struct Headers(Vec<(String, String)>);
+
+pub struct Iter<'a>(slice::Iter<'a, (String, String)>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, val)| (key, val))
+    }
+}
 
 impl Headers {
     pub fn new() -> Headers {
-        Headers(HashMap::new())
+        Headers(Vec::new())
     }
 
     pub fn with_capacity(capacity: usize) -> Headers {
-        Headers(HashMap::with_capacity(capacity))
+        Headers(Vec::with_capacity(capacity))
     }
 
-    pub fn iter(&self) -> hash_map::Iter<String, String> {
-        self.0.iter()
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.0.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains_key<T: ToString + ?Sized>(&self, k: &T) -> bool {
+        let k = k.to_string();
+        self.0.iter().any(|(key, _)| key.eq_ignore_ascii_case(&k))
     }
 
     pub fn get<T: ToString + ?Sized>(&self, k: &T) -> Option<String> {
-        match self.0.get(&k.to_string().to_lowercase()) {
-            Some(value) => Some(value.to_string()),
-            None => None,
-        }
+        let k = k.to_string();
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&k))
+            .map(|(_, val)| val.clone())
     }
 
+    pub fn get_all<T: ToString + ?Sized>(&self, k: &T) -> Vec<String> {
+        let k = k.to_string();
+        self.0
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(&k))
+            .map(|(_, val)| val.clone())
+            .collect()
+    }
+
+    // Replaces every value of the header, keeping the position of the first one.
+    // The name is sent exactly as given here.
     pub fn insert<T: ToString + ?Sized, U: ToString + ?Sized>(
         &mut self,
         key: &T,
         val: &U,
     ) -> Option<String> {
-        self.0
-            .insert(key.to_string().to_lowercase(), val.to_string())
+        let key = key.to_string();
+        match self
+            .0
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(&key))
+        {
+            Some(idx) => {
+                let mut first = true;
+                self.0.retain(|(k, _)| {
+                    !k.eq_ignore_ascii_case(&key) || mem::replace(&mut first, false)
+                });
+                Some(mem::replace(&mut self.0[idx], (key, val.to_string())).1)
+            }
+            None => {
+                self.0.push((key, val.to_string()));
+                None
+            }
+        }
+    }
+
+    pub fn append<T: ToString + ?Sized, U: ToString + ?Sized>(&mut self, key: &T, val: &U) {
+        self.0.push((key.to_string(), val.to_string()));
+    }
+
+    pub fn remove<T: ToString + ?Sized>(&mut self, k: &T) -> Option<String> {
+        let k = k.to_string();
+        let old = self.get(&k);
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(&k));
+        old
+    }
+
+    // Replaces headers present in `other` with all of their values from it.
+    pub fn merge(&mut self, other: &Headers) {
+        for (key, _) in other.iter() {
+            self.remove(key);
+        }
+        for (key, val) in other.iter() {
+            self.append(key, val);
+        }
+    }
+
+    // Lists headers named in `order` first, in that order, followed by the rest
+    // in insertion order.
+    pub fn ordered<T: AsRef<str>>(&self, order: &[T]) -> Vec<(&String, &String)> {
+        let mut result = Vec::with_capacity(self.len());
+        for name in order {
+            result.extend(
+                self.iter()
+                    .filter(|(key, _)| key.eq_ignore_ascii_case(name.as_ref())),
+            );
+        }
+        result.extend(self.iter().filter(|(key, _)| {
+            !order
+                .iter()
+                .any(|name| key.eq_ignore_ascii_case(name.as_ref()))
+        }));
+        result
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (key, val) in self.iter() {
+            if !is_token(key) {
+                return Err(Error::InvalidHeaderName(key.clone()));
+            }
+            if !is_field_value(val) {
+                return Err(Error::InvalidHeaderValue(key.clone()));
+            }
+        }
+        Ok(())
     }
 
     pub fn default_http(host: &str) -> Headers {
@@ -44,6 +188,15 @@ impl Headers {
     }
 }
 
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
 impl FromStr for Headers {
     type Err = Error;
 
@@ -56,10 +209,7 @@ impl FromStr for Headers {
                 .map(|elem| {
                     let idx = elem.find(':').unwrap();
                     let (key, value) = elem.split_at(idx);
-                    (
-                        key.to_string().to_lowercase(),
-                        value[1..].trim().to_string(),
-                    )
+                    (key.to_string(), value[1..].trim().to_string())
                 })
                 .collect();
 
@@ -72,13 +222,21 @@ impl FromStr for Headers {
 
 impl From<HashMap<String, String>> for Headers {
     fn from(map: HashMap<String, String>) -> Headers {
-        Headers(map)
+        let mut headers = Headers::with_capacity(map.len());
+        for (key, val) in map {
+            headers.insert(&key, &val);
+        }
+        headers
     }
 }
 
 impl From<Headers> for HashMap<String, String> {
-    fn from(map: Headers) -> HashMap<String, String> {
-        map.0
+    fn from(headers: Headers) -> HashMap<String, String> {
+        let mut map = HashMap::with_capacity(headers.len());
+        for (key, val) in headers.0 {
+            map.entry(key.to_lowercase()).or_insert(val);
+        }
+        map
     }
 }
 
@@ -92,3 +250,73 @@ impl Display for Headers {
         write!(f, "{{\r\n{}}}", headers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_order() {
+        let mut headers = Headers::new();
+        headers.insert("Host", "example.org");
+        headers.insert("Accept", "*/*");
+        headers.insert("User-Agent", "rp");
+        headers.insert("host", "example.com");
+        let keys: Vec<&String> = headers.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["host", "Accept", "User-Agent"]);
+        let keys: Vec<&String> = headers
+            .ordered(&["user-agent", "HOST"])
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["User-Agent", "host", "Accept"]);
+        assert_eq!(headers.get("HOST"), Some("example.com".to_string()));
+    }
+
+    #[test]
+    fn headers_case() {
+        assert_eq!(
+            HeaderCase::Title.apply("x-forwarded-FOR"),
+            "X-Forwarded-For"
+        );
+        assert_eq!(HeaderCase::Lower.apply("Content-Type"), "content-type");
+        assert_eq!(HeaderCase::Preserve.apply("X-ID"), "X-ID");
+        let headers = "X-Custom-ID: 1".parse::<Headers>().unwrap();
+        assert_eq!(headers.get("x-custom-id"), Some("1".to_string()));
+        assert_eq!(headers.iter().next().unwrap().0, "X-Custom-ID");
+    }
+
+    #[test]
+    fn headers_validate() {
+        let mut headers = Headers::new();
+        headers.insert("X-Ok", "a\tb \u{444}");
+        assert!(headers.validate().is_ok());
+        headers.insert("X-Bad", "a\r\nInjected: 1");
+        match headers.validate() {
+            Err(Error::InvalidHeaderValue(name)) => assert_eq!(name, "X-Bad"),
+            r => panic!("{:?}", r),
+        }
+        let mut headers = Headers::new();
+        headers.insert("Bad Name", "1");
+        assert!(matches!(
+            headers.validate(),
+            Err(Error::InvalidHeaderName(_))
+        ));
+    }
+
+    #[test]
+    fn headers_multi() {
+        let mut headers = "Via: 1.0 a\r\nSet-Cookie: a=1\r\nVia: 1.1 b\r\nSet-Cookie: b=2"
+            .parse::<Headers>()
+            .unwrap();
+        assert_eq!(headers.get_all("set-cookie"), vec!["a=1", "b=2"]);
+        assert_eq!(headers.get("Via"), Some("1.0 a".to_string()));
+        headers.append("Via", "1.1 c");
+        assert_eq!(headers.get_all("via").len(), 3);
+        headers.insert("Via", "2.0 d");
+        assert_eq!(headers.get_all("via"), vec!["2.0 d"]);
+        assert_eq!(headers.remove("Set-Cookie"), Some("a=1".to_string()));
+        assert!(!headers.contains_key("set-cookie"));
+        assert_eq!(headers.len(), 1);
+    }
+}
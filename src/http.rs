@@ -1,6 +1,7 @@
 use std::net::TcpStream;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::limits::Limits;
 use crate::response::Response;
 use crate::stream::Stream;
 use crate::uri::Uri;
@@ -29,6 +30,26 @@ impl HttpStream {
         Ok(HttpStream { stream })
     }
 
+    // Opens a tunnel to the target through an HTTP proxy with CONNECT.
+    pub fn tunnel(proxy: &Uri, target: &Uri, limits: &Limits) -> Result<Self> {
+        let stream = TcpStream::connect(proxy.socket_addr()?)?;
+        let mut stream = Stream::new_tcp(stream);
+        Stream::send_msg(&mut stream, connect_request(proxy, target).as_bytes())?;
+        let response = Stream::read_head_with(&mut stream, limits)?;
+        if !response.status_code().is_success() {
+            return Err(Error::UnexpectedStatus(response.status_code()));
+        }
+        let stream = match stream {
+            Stream::Tcp(stream) if target.is_ssl() => Stream::new_tls(target.host(), stream)?,
+            stream => stream,
+        };
+        Ok(HttpStream { stream })
+    }
+
+    pub fn stream_mut(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+
     pub fn send_request(&mut self, req: &[u8]) -> Result<()> {
         Stream::send_msg(&mut self.stream, req)
     }
@@ -42,10 +63,35 @@ impl HttpStream {
     }
 }
 
+fn connect_request(proxy: &Uri, target: &Uri) -> String {
+    let mut msg = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target.host_port());
+    if let Some(auth) = proxy.base64_auth() {
+        msg.push_str(&format!("Proxy-Authorization: Basic {}\r\n", auth));
+    }
+    msg.push_str("\r\n");
+    msg
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn http_stream_tunnel_limits() {
+        let (proxy, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 Connection established\r\nX-Padding: 0123456789\r\n\r\n".to_vec(),
+        ]);
+        let limits = Limits {
+            header_bytes: 16,
+            ..Limits::default()
+        };
+        let target = "http://example.com".parse::<Uri>().unwrap();
+        match HttpStream::tunnel(&proxy.parse().unwrap(), &target, &limits) {
+            Err(Error::HeadersTooLarge(16)) => (),
+            r => panic!("{:?}", r.map(|_| ())),
+        }
+    }
+
     #[test]
     fn http_stream_http() {
         let mut client =
@@ -1,22 +1,49 @@
 pub mod addr;
+#[cfg(feature = "tokio")]
+pub mod async_client;
+#[cfg(feature = "tokio")]
+pub mod async_stream;
+pub mod auth;
 pub mod authority;
+pub mod batch;
+pub mod body;
+pub mod cache;
+pub mod callback;
+pub mod charset;
 pub mod client;
 pub mod client_builder;
+pub mod cookie;
+pub mod date;
+pub mod download;
+pub mod encoding;
 pub mod error;
+pub mod hash;
 pub mod headers;
 pub mod http;
+pub mod limits;
 pub mod method;
+pub mod multipart;
+pub mod percent;
+pub mod progress;
 pub mod proxy;
+pub mod random;
 pub mod range;
+pub mod rate_limit;
+pub mod redirect;
 pub mod request;
 pub mod response;
+pub mod retry;
 pub mod socks;
+pub mod sse;
 pub mod status;
 pub mod stream;
 pub mod transport;
+pub mod upload;
 pub mod uri;
 pub mod userinfo;
 pub mod version;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 #[cfg(test)]
 #[macro_use]
@@ -41,7 +68,47 @@ pub(crate) fn my_ip() -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
     lazy_static! {
         pub static ref IP: String = crate::my_ip();
     }
+
+    pub fn serve(responses: Vec<Vec<u8>>) -> (String, Receiver<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    request.push(byte[0]);
+                }
+                let head = String::from_utf8_lossy(&request).to_lowercase();
+                if let Some(len) = head
+                    .lines()
+                    .find(|line| line.starts_with("content-length:"))
+                    .and_then(|line| line[15..].trim().parse::<usize>().ok())
+                {
+                    let mut body = vec![0u8; len];
+                    stream.read_exact(&mut body).unwrap();
+                    request.extend(body);
+                } else if head.contains("transfer-encoding: chunked") {
+                    while !request.ends_with(b"\r\n0\r\n\r\n")
+                        && stream.read(&mut byte).unwrap() == 1
+                    {
+                        request.push(byte[0]);
+                    }
+                }
+                stream.write_all(&response).unwrap();
+                let _ = tx.send(request);
+            }
+        });
+        (format!("http://{}", addr), rx)
+    }
 }
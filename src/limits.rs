@@ -0,0 +1,168 @@
+use std::io::{self, Read};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub status_line: usize,
+    pub header_count: usize,
+    pub header_bytes: usize,
+    pub body_bytes: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            status_line: 8 * 1024,
+            header_count: 100,
+            header_bytes: 64 * 1024,
+            body_bytes: None,
+        }
+    }
+}
+
+// Reads a response head up to and including the empty line that ends it.
+pub fn read_head<R: Read + ?Sized>(reader: &mut R, limits: &Limits) -> Result<Vec<u8>> {
+    let mut head = HeadBuf::new(limits);
+    let mut byte = [0u8; 1];
+    while reader.read(&mut byte)? == 1 {
+        if head.push(byte[0])? {
+            break;
+        }
+    }
+    head.finish()
+}
+
+// Collects a response head one byte at a time, checking it against the
+// limits, for readers that can't use read_head.
+#[derive(Debug)]
+pub struct HeadBuf<'a> {
+    limits: &'a Limits,
+    head: Vec<u8>,
+    line_start: usize,
+    headers: usize,
+}
+
+impl<'a> HeadBuf<'a> {
+    pub fn new(limits: &'a Limits) -> HeadBuf<'a> {
+        HeadBuf {
+            limits,
+            head: Vec::with_capacity(200),
+            line_start: 0,
+            headers: 0,
+        }
+    }
+
+    // Returns true once the empty line that ends the head has been pushed.
+    pub fn push(&mut self, byte: u8) -> Result<bool> {
+        let limits = self.limits;
+        self.head.push(byte);
+        if self.head.len() > limits.header_bytes {
+            return Err(Error::HeadersTooLarge(limits.header_bytes));
+        }
+        if self.line_start == 0 && self.head.len() > limits.status_line {
+            return Err(Error::StatusLineTooLong(limits.status_line));
+        }
+        if byte == b'\n' {
+            if self.line_start > 0 && self.head.len() - self.line_start > 2 {
+                self.headers += 1;
+                if self.headers > limits.header_count {
+                    return Err(Error::TooManyHeaders(limits.header_count));
+                }
+            }
+            self.line_start = self.head.len();
+        }
+        Ok(self.head.ends_with(b"\r\n\r\n"))
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>> {
+        if self.head.is_empty() {
+            Err(Error::EmptyResponse)
+        } else {
+            Ok(self.head)
+        }
+    }
+}
+
+pub struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner,
+            limit,
+            remaining: limit,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::BodyTooLarge(self.limit),
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_head() {
+        let head = b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\n\r\nbody";
+        let limits = Limits::default();
+        let read = read_head(&mut &head[..], &limits).unwrap();
+        assert_eq!(read, &head[..head.len() - 4]);
+
+        let limits = Limits {
+            status_line: 10,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            read_head(&mut &head[..], &limits),
+            Err(Error::StatusLineTooLong(10))
+        ));
+
+        let limits = Limits {
+            header_count: 1,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            read_head(&mut &head[..], &limits),
+            Err(Error::TooManyHeaders(1))
+        ));
+
+        let limits = Limits {
+            header_bytes: 20,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            read_head(&mut &head[..], &limits),
+            Err(Error::HeadersTooLarge(20))
+        ));
+    }
+
+    #[test]
+    fn limits_body() {
+        let mut body = String::new();
+        LimitedReader::new(&b"hello"[..], 5)
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "hello");
+        let err = LimitedReader::new(&b"hello!"[..], 5)
+            .read_to_string(&mut body)
+            .unwrap_err();
+        assert!(matches!(Error::from(err), Error::BodyTooLarge(5)));
+    }
+}
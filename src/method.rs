@@ -2,11 +2,13 @@ use std::fmt;
 use std::str::FromStr;
 
 use crate::error::{Error, Result};
+use crate::headers::is_token;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum Method {
     CONNECT,
     DELETE,
+    #[default]
     GET,
     HEAD,
     OPTIONS,
@@ -14,6 +16,7 @@ pub enum Method {
     POST,
     PUT,
     TRACE,
+    Custom(String),
 }
 
 pub trait IntoMethod {
@@ -26,7 +29,7 @@ impl IntoMethod for Method {
     }
 }
 
-impl<'a> IntoMethod for &'a str {
+impl IntoMethod for &str {
     fn into_method(self) -> Result<Method> {
         self.parse()
     }
@@ -50,16 +53,11 @@ impl Method {
             Method::POST => "POST",
             Method::PUT => "PUT",
             Method::TRACE => "TRACE",
+            Method::Custom(method) => method,
         }
     }
 }
 
-impl Default for Method {
-    fn default() -> Self {
-        Method::GET
-    }
-}
-
 impl FromStr for Method {
     type Err = Error;
 
@@ -74,6 +72,7 @@ impl FromStr for Method {
             "POST" => Ok(Method::POST),
             "PUT" => Ok(Method::PUT),
             "TRACE" => Ok(Method::TRACE),
+            _ if is_token(s) => Ok(Method::Custom(s.to_owned())),
             _ => Err(Error::UnknownMethod(s.to_owned())),
         }
     }
@@ -81,17 +80,6 @@ impl FromStr for Method {
 
 impl fmt::Display for Method {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let method = match self {
-            Method::CONNECT => "CONNECT",
-            Method::DELETE => "DELETE",
-            Method::GET => "GET",
-            Method::HEAD => "HEAD",
-            Method::OPTIONS => "OPTIONS",
-            Method::PATCH => "PATCH",
-            Method::POST => "POST",
-            Method::PUT => "PUT",
-            Method::TRACE => "TRACE",
-        };
-        write!(f, "{}", method)
+        write!(f, "{}", self.as_str())
     }
 }
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::random::random_u64;
+
+enum PartBody {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+pub struct Part {
+    body: PartBody,
+    len: Option<u64>,
+    file_name: Option<String>,
+    mime: Option<String>,
+}
+
+impl Part {
+    pub fn text<T: Into<String>>(value: T) -> Part {
+        Part::bytes(value.into().into_bytes())
+    }
+
+    pub fn bytes<T: Into<Vec<u8>>>(value: T) -> Part {
+        let value = value.into();
+        Part {
+            len: Some(value.len() as u64),
+            body: PartBody::Bytes(value),
+            file_name: None,
+            mime: None,
+        }
+    }
+
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Part {
+        Part {
+            body: PartBody::Reader(Box::new(reader)),
+            len: None,
+            file_name: None,
+            mime: None,
+        }
+    }
+
+    pub fn reader_with_len<R: Read + Send + 'static>(reader: R, len: u64) -> Part {
+        Part {
+            len: Some(len),
+            ..Part::reader(reader)
+        }
+    }
+
+    pub fn file<P: AsRef<Path>>(path: P) -> Result<Part> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut part = Part::reader_with_len(file, len).mime(mime_from_path(path));
+        if let Some(name) = path.file_name() {
+            part = part.file_name(name.to_string_lossy());
+        }
+        Ok(part)
+    }
+
+    pub fn file_name<T: Into<String>>(mut self, file_name: T) -> Part {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    pub fn mime<T: Into<String>>(mut self, mime: T) -> Part {
+        self.mime = Some(mime.into());
+        self
+    }
+
+    fn head(&self, boundary: &str, name: &str) -> Vec<u8> {
+        let mut head = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            boundary,
+            escape(name)
+        );
+        if let Some(file_name) = &self.file_name {
+            head.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+        if let Some(mime) = &self.mime {
+            head.push_str(&format!("\r\nContent-Type: {}", mime));
+        }
+        head.push_str("\r\n\r\n");
+        head.into_bytes()
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("len", &self.len)
+            .field("file_name", &self.file_name)
+            .field("mime", &self.mime)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Form::new()
+    }
+}
+
+impl Form {
+    pub fn new() -> Form {
+        Form {
+            boundary: format!("{:016x}{:016x}", random_u64(), random_u64()),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    pub fn text<T: Into<String>, U: Into<String>>(self, name: T, value: U) -> Form {
+        self.part(name, Part::text(value))
+    }
+
+    pub fn file<T: Into<String>, P: AsRef<Path>>(self, name: T, path: P) -> Result<Form> {
+        Ok(self.part(name, Part::file(path)?))
+    }
+
+    pub fn part<T: Into<String>>(mut self, name: T, part: Part) -> Form {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn content_len(&self) -> Option<u64> {
+        let mut len = 0;
+        for (name, part) in &self.parts {
+            len += part.head(&self.boundary, name).len() as u64 + part.len? + 2;
+        }
+        Some(len + self.boundary.len() as u64 + 6)
+    }
+
+    pub fn reader(self) -> FormReader {
+        let mut readers: VecDeque<Box<dyn Read + Send>> = VecDeque::new();
+        for (name, part) in self.parts {
+            readers.push_back(Box::new(Cursor::new(part.head(&self.boundary, &name))));
+            match part.body {
+                PartBody::Bytes(bytes) => readers.push_back(Box::new(Cursor::new(bytes))),
+                PartBody::Reader(reader) => match part.len {
+                    Some(len) => readers.push_back(Box::new(ExactReader {
+                        inner: reader.take(len),
+                        remaining: len,
+                    })),
+                    None => readers.push_back(reader),
+                },
+            }
+            readers.push_back(Box::new(Cursor::new(b"\r\n".to_vec())));
+        }
+        readers.push_back(Box::new(Cursor::new(
+            format!("--{}--\r\n", self.boundary).into_bytes(),
+        )));
+        FormReader { readers }
+    }
+}
+
+pub struct FormReader {
+    readers: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl Read for FormReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(reader) = self.readers.front_mut() {
+            let read = reader.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.readers.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+// Reads a part with a declared length, failing if it ends early so the body
+// never falls short of the Content-Length sent for it.
+struct ExactReader<R> {
+    inner: io::Take<R>,
+    remaining: u64,
+}
+
+impl<R: Read> Read for ExactReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read == 0 && self.remaining > 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("part ended {} bytes short", self.remaining),
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn mime_from_path(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "txt" => "text/plain",
+        "htm" | "html" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_body() {
+        let form = Form::new().text("name", "value").part(
+            "file",
+            Part::bytes(b"data".to_vec())
+                .file_name("a\"b.txt")
+                .mime("text/plain"),
+        );
+        let boundary = form.boundary().to_string();
+        let len = form.content_len().unwrap();
+        let mut body = String::new();
+        form.reader().read_to_string(&mut body).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nvalue\r\n\
+                 --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a%22b.txt\"\r\n\
+                 Content-Type: text/plain\r\n\r\ndata\r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+        assert_eq!(len, body.len() as u64);
+    }
+
+    #[test]
+    fn form_unknown_len() {
+        let form = Form::new().part("r", Part::reader(Cursor::new(b"abc".to_vec())));
+        assert_eq!(form.content_len(), None);
+        let mut body = String::new();
+        form.reader().read_to_string(&mut body).unwrap();
+        assert!(body.contains("\r\n\r\nabc\r\n"));
+    }
+
+    #[test]
+    fn form_short_reader() {
+        let part = Part::reader_with_len(Cursor::new(b"abc".to_vec()), 5);
+        let mut body = Vec::new();
+        let e = Form::new().part("r", part).reader().read_to_end(&mut body);
+        assert_eq!(e.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn form_send() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let form = Form::new().text("a", "b");
+        let content_type = form.content_type();
+        let mut client = crate::client::Client::new(uri.as_str())
+            .post(uri.as_str())
+            .multipart(form)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains(&content_type));
+        assert!(request.contains("name=\"a\"\r\n\r\nb\r\n"));
+        assert!(request.ends_with("--\r\n"));
+    }
+
+    #[test]
+    fn form_boundary_unique() {
+        assert_ne!(Form::new().boundary(), Form::new().boundary());
+    }
+}
@@ -0,0 +1,86 @@
+// Unreserved characters from RFC 3986, section 2.3.
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+// The application/x-www-form-urlencoded set from the WHATWG URL standard.
+fn is_form_safe(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'*' | b'-' | b'.' | b'_')
+}
+
+fn encode_with(input: &str, keep: fn(u8) -> bool, space_as_plus: bool) -> String {
+    let mut result = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        if keep(b) {
+            result.push(b as char);
+        } else if b == b' ' && space_as_plus {
+            result.push('+');
+        } else {
+            result.push_str(&format!("%{:02X}", b));
+        }
+    }
+    result
+}
+
+pub fn encode(input: &str) -> String {
+    encode_with(input, is_unreserved, false)
+}
+
+pub fn encode_form(input: &str) -> String {
+    encode_with(input, is_form_safe, true)
+}
+
+pub fn encode_pairs<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode_form(k.as_ref()), encode_form(v.as_ref())))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+pub fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(h), Some(l)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                result.push((h << 4) | l);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode() {
+        assert_eq!(encode("a b&c/d~"), "a%20b%26c%2Fd~");
+        assert_eq!(encode("ключ"), "%D0%BA%D0%BB%D1%8E%D1%87");
+    }
+
+    #[test]
+    fn percent_encode_pairs() {
+        assert_eq!(
+            encode_pairs(&[("q", "rust lang"), ("a&b", "c=d*~")]),
+            "q=rust+lang&a%26b=c%3Dd*%7E"
+        );
+    }
+
+    #[test]
+    fn percent_decode() {
+        assert_eq!(decode("a%20b%2fc%"), "a b/c%");
+        assert_eq!(decode("%D0%BA%zz"), "к%zz");
+    }
+}
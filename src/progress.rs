@@ -0,0 +1,98 @@
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use crate::callback::Callback;
+use crate::download::parse_content_range;
+use crate::response::Response;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub bytes: u64,
+    pub total: Option<u64>,
+    pub elapsed: Duration,
+}
+
+pub struct ProgressReader<R> {
+    inner: R,
+    callback: Callback<Progress>,
+    bytes: u64,
+    total: Option<u64>,
+    start: Instant,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, callback: Callback<Progress>, total: Option<u64>) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            callback,
+            bytes: 0,
+            total,
+            start: Instant::now(),
+        }
+    }
+
+    // Counts from `bytes` on, e.g. when a response continues a partial download.
+    pub fn offset(mut self, bytes: u64) -> ProgressReader<R> {
+        self.bytes = bytes;
+        self
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.bytes += read as u64;
+            self.callback.call(&Progress {
+                bytes: self.bytes,
+                total: self.total,
+                elapsed: self.start.elapsed(),
+            });
+        }
+        Ok(read)
+    }
+}
+
+// Returns the offset and the total size of the body, taking Content-Range of
+// a partial response into account.
+pub fn response_range(response: &Response, content_len: Option<u64>) -> (u64, Option<u64>) {
+    if u16::from(response.status_code()) == 206 {
+        if let Some((first, _, total)) = response
+            .headers()
+            .get("Content-Range")
+            .and_then(|range| parse_content_range(&range))
+        {
+            return (first, total.or_else(|| content_len.map(|len| first + len)));
+        }
+    }
+    (0, content_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn progress_reader() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let callback =
+            Callback::new(move |p: &Progress| log.lock().unwrap().push((p.bytes, p.total)));
+        let mut reader = ProgressReader::new(&b"hello world"[..], callback, Some(15)).offset(4);
+        let mut buf = [0u8; 6];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(*seen.lock().unwrap(), vec![(10, Some(15)), (15, Some(15))]);
+    }
+
+    #[test]
+    fn progress_partial() {
+        let response = Response::from_head(
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 100-199/1000\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(response_range(&response, Some(100)), (100, Some(1000)));
+        let response = Response::from_head(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+        assert_eq!(response_range(&response, Some(100)), (0, Some(100)));
+    }
+}
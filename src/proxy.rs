@@ -2,6 +2,7 @@ use crate::error::{Error, Result};
 use crate::http::HttpStream;
 use crate::response::Response;
 use crate::socks::SocksStream;
+use crate::stream::Stream;
 use crate::uri::Uri;
 
 #[derive(Debug)]
@@ -22,6 +23,7 @@ pub enum Proxy {
 }
 
 impl Proxy {
+    #[allow(clippy::self_named_constructors)]
     pub fn proxy(proxy: &Uri, target: &Uri) -> Result<Proxy> {
         match proxy.scheme() {
             "http" => Proxy::http(proxy),
@@ -56,6 +58,14 @@ impl Proxy {
         }))
     }
 
+    pub fn stream_mut(&mut self) -> &mut Stream {
+        match self {
+            Proxy::Http(http_proxy) => http_proxy.stream.stream_mut(),
+            Proxy::Https(http_proxy) => http_proxy.stream.stream_mut(),
+            Proxy::Socks(socks_proxy) => socks_proxy.stream.stream_mut(),
+        }
+    }
+
     pub fn send_request(&mut self, req: &[u8]) -> Result<()> {
         match self {
             Proxy::Http(http_proxy) => http_proxy.stream.send_request(req),
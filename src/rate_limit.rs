@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::uri::Uri;
+
+// Full buckets are dropped once there are more than this many.
+const MAX_BUCKETS: usize = 1024;
+
+// What requests share a bucket. Direct connections share the proxy bucket
+// of an empty proxy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateKey {
+    Host,
+    Proxy,
+    HostAndProxy,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// A token bucket limiter for new connections. Clones share the buckets, so
+// one limiter can be given to many clients.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    // Tokens added per second.
+    rate: f64,
+    burst: f64,
+    key: RateKey,
+    block: bool,
+}
+
+impl RateLimiter {
+    // Allows `requests` connections each `per`, keyed by target host and
+    // blocking until a token is free.
+    pub fn new(requests: u32, per: Duration) -> RateLimiter {
+        let requests = requests.max(1) as f64;
+        let per = per.as_secs_f64().max(f64::MIN_POSITIVE);
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            rate: requests / per,
+            burst: requests,
+            key: RateKey::Host,
+            block: true,
+        }
+    }
+
+    // Most connections that may be opened at once after being idle.
+    pub fn burst(mut self, burst: u32) -> RateLimiter {
+        self.burst = burst.max(1) as f64;
+        self
+    }
+
+    pub fn key(mut self, key: RateKey) -> RateLimiter {
+        self.key = key;
+        self
+    }
+
+    // When disabled, acquire fails with Error::RateLimited instead of waiting.
+    pub fn block(mut self, enable: bool) -> RateLimiter {
+        self.block = enable;
+        self
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.block
+    }
+
+    // Takes a token for a connection to uri, waiting for one or failing as
+    // configured.
+    pub fn acquire(&self, uri: &Uri, proxy: Option<&Uri>) -> Result<()> {
+        loop {
+            match self.try_acquire(uri, proxy) {
+                Err(Error::RateLimited(wait)) if self.block => thread::sleep(wait),
+                result => return result,
+            }
+        }
+    }
+
+    // Takes a token if one is free, or fails with Error::RateLimited and the
+    // time until there is one.
+    pub fn try_acquire(&self, uri: &Uri, proxy: Option<&Uri>) -> Result<()> {
+        let key = self.bucket_key(uri, proxy);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_BUCKETS {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets.retain(|_, b| b.tokens + elapsed(b, now) * rate < burst);
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens + elapsed(bucket, now) * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.rate;
+            let wait = Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX);
+            Err(Error::RateLimited(wait))
+        }
+    }
+
+    fn bucket_key(&self, uri: &Uri, proxy: Option<&Uri>) -> String {
+        let proxy = proxy.map(Uri::host_port).unwrap_or_default();
+        match self.key {
+            RateKey::Host => uri.host_port(),
+            RateKey::Proxy => proxy,
+            RateKey::HostAndProxy => format!("{} {}", proxy, uri.host_port()),
+        }
+    }
+}
+
+fn elapsed(bucket: &Bucket, now: Instant) -> f64 {
+    now.saturating_duration_since(bucket.updated).as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::uri::IntoUri;
+
+    #[test]
+    fn rate_limit_keys() {
+        let a = "http://a.com/".into_uri().unwrap();
+        let b = "http://b.com/".into_uri().unwrap();
+        let proxy = "socks5://127.0.0.1:1080".into_uri().unwrap();
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).block(false);
+        assert!(limiter.acquire(&a, None).is_ok());
+        assert!(limiter.acquire(&b, Some(&proxy)).is_ok());
+        match limiter.acquire(&a, Some(&proxy)) {
+            Err(Error::RateLimited(wait)) => assert!(wait > Duration::from_secs(50)),
+            other => panic!("{:?}", other),
+        }
+
+        let limiter = RateLimiter::new(1, Duration::from_secs(60))
+            .key(RateKey::Proxy)
+            .block(false);
+        assert!(limiter.acquire(&a, Some(&proxy)).is_ok());
+        assert!(limiter.acquire(&b, None).is_ok());
+        assert!(limiter.acquire(&b, Some(&proxy)).is_err());
+
+        let limiter = RateLimiter::new(1, Duration::from_secs(60))
+            .key(RateKey::HostAndProxy)
+            .block(false);
+        assert!(limiter.acquire(&a, Some(&proxy)).is_ok());
+        assert!(limiter.acquire(&a, None).is_ok());
+        assert!(limiter.acquire(&b, Some(&proxy)).is_ok());
+        assert!(limiter.clone().acquire(&a, Some(&proxy)).is_err());
+    }
+
+    #[test]
+    fn rate_limit_block() {
+        let uri = "http://a.com/".into_uri().unwrap();
+        let limiter = RateLimiter::new(20, Duration::from_secs(1)).burst(2);
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(&uri, None).unwrap();
+        }
+        // Two tokens from the burst, then one each 50ms.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn rate_limit_long_wait() {
+        let uri = "http://a.com/".into_uri().unwrap();
+        let limiter = RateLimiter::new(1, Duration::MAX).block(false);
+        limiter.acquire(&uri, None).unwrap();
+        assert!(matches!(
+            limiter.acquire(&uri, None),
+            Err(Error::RateLimited(Duration::MAX))
+        ));
+    }
+
+    #[test]
+    fn rate_limit_client() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).block(false);
+        let mut client = Client::new(uri.as_str())
+            .rate_limit(&limiter)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert!(matches!(client.reconnect(), Err(Error::RateLimited(_))));
+        let other = Client::new(uri.as_str()).rate_limit(&limiter).build();
+        assert!(matches!(other, Err(Error::RateLimited(_))));
+    }
+}
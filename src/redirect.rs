@@ -0,0 +1,217 @@
+use crate::error::{Error, Result};
+use crate::method::Method;
+use crate::request::Request;
+use crate::response::Response;
+use crate::uri::Uri;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RedirectPolicy {
+    #[default]
+    None,
+    Limited(usize),
+}
+
+impl RedirectPolicy {
+    pub fn none() -> Self {
+        RedirectPolicy::None
+    }
+
+    pub fn limited(max: usize) -> Self {
+        RedirectPolicy::Limited(max)
+    }
+
+    pub fn location(&self, response: &Response, uri: &Uri, chain: &[Uri]) -> Result<Option<Uri>> {
+        let max = match self {
+            RedirectPolicy::None => return Ok(None),
+            RedirectPolicy::Limited(max) => *max,
+        };
+        if !is_redirect(response) {
+            return Ok(None);
+        }
+        let location = match response.headers().get("Location") {
+            Some(location) => uri.join(&location)?,
+            None => return Ok(None),
+        };
+        match location.scheme() {
+            "http" | "https" => (),
+            s => return Err(Error::UnsupportedScheme(s.to_string())),
+        }
+        if chain.len() >= max {
+            return Err(Error::TooManyRedirects(max));
+        }
+        // A redirect to the current uri, e.g. after setting a cookie, is
+        // followed once; coming back to it again is a loop.
+        if chain
+            .iter()
+            .any(|u| u.without_fragment() == location.without_fragment())
+        {
+            return Err(Error::RedirectLoop(location.to_string()));
+        }
+        Ok(Some(location))
+    }
+}
+
+pub fn is_redirect(response: &Response) -> bool {
+    matches!(
+        u16::from(response.status_code()),
+        301 | 302 | 303 | 307 | 308
+    )
+}
+
+pub fn redirect_request(request: &Request, response: &Response, from: &Uri, to: &Uri) -> Request {
+    let mut request = request.clone();
+    request.uri(to);
+
+    let code = u16::from(response.status_code());
+    let method = request.get_method().clone();
+    let to_get = match code {
+        301 | 302 => method == Method::POST,
+        303 => method != Method::HEAD && method != Method::GET,
+        _ => false,
+    };
+    if to_get {
+        request.method(Method::GET);
+        request.body(None);
+        request.remove_header("Content-Length");
+        request.remove_header("Content-Type");
+        request.remove_header("Transfer-Encoding");
+    }
+
+    if from.origin() != to.origin() {
+        request.remove_header("Authorization");
+    }
+
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::cookie::CookieJar;
+
+    #[test]
+    fn redirect_relative() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next?a=1\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec(),
+        ]);
+        let mut client = Client::new(format!("{}/start", uri))
+            .redirect(RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        assert_eq!(response.redirects().len(), 1);
+        assert_eq!(response.redirects()[0].path(), Some("/start"));
+        assert_eq!(client.uri().path(), Some("/next"));
+        assert_eq!(client.text().unwrap(), "ok");
+        rx.recv().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.contains("/next?a=1 HTTP/1.1"));
+    }
+
+    #[test]
+    fn redirect_see_other() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 303 See Other\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .post(uri.as_str())
+            .header("Content-Length", "4")
+            .body(b"data")
+            .redirect(RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        rx.recv().unwrap();
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.starts_with("GET "));
+        assert!(!request.to_lowercase().contains("content-length"));
+    }
+
+    #[test]
+    fn redirect_cross_origin() {
+        let (other, rx_other) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let location = format!(
+            "HTTP/1.1 307 Temporary Redirect\r\nLocation: {}/\r\nContent-Length: 0\r\n\r\n",
+            other
+        );
+        let (uri, rx) = crate::tests::serve(vec![location.into_bytes()]);
+        let mut client = Client::new(uri.as_str())
+            .header("Authorization", "Bearer secret")
+            .redirect(RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        let first = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(first.to_lowercase().contains("authorization"));
+        let second = String::from_utf8(rx_other.recv().unwrap()).unwrap();
+        assert!(!second.to_lowercase().contains("authorization"));
+    }
+
+    #[test]
+    fn redirect_loop() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 302 Found\r\nLocation: /a\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(format!("{}/a", uri))
+            .redirect(RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        match client.send() {
+            Err(Error::RedirectLoop(_)) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn redirect_to_self() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nSet-Cookie: session=1\r\nLocation: /a\r\n\
+              Content-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(format!("{}/a", uri))
+            .cookie_jar(&CookieJar::new())
+            .redirect(RedirectPolicy::limited(5))
+            .build()
+            .unwrap();
+        let response = client.send().unwrap();
+        assert_eq!(u16::from(response.status_code()), 200);
+        assert_eq!(response.redirects().len(), 1);
+        rx.recv().unwrap();
+        let second = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(second.contains("Cookie: session=1\r\n"));
+    }
+
+    #[test]
+    fn redirect_limit() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(format!("{}/a", uri))
+            .redirect(RedirectPolicy::limited(0))
+            .build()
+            .unwrap();
+        match client.send() {
+            Err(Error::TooManyRedirects(0)) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn redirect_none() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str()).build().unwrap();
+        let response = client.send().unwrap();
+        assert_eq!(u16::from(response.status_code()), 302);
+    }
+}
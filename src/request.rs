@@ -1,4 +1,5 @@
-use crate::headers::Headers;
+use crate::error::{Error, Result};
+use crate::headers::{is_token, HeaderCase, Headers};
 use crate::method::Method;
 use crate::uri::Uri;
 use crate::version::Version;
@@ -11,8 +12,9 @@ pub struct Request {
     request_uri: String,
     version: Version,
     headers: Headers,
+    header_case: HeaderCase,
+    header_order: Vec<String>,
     host: String,
-    content_len: usize,
     body: Option<Vec<u8>>,
     using_proxy: bool,
 }
@@ -20,7 +22,7 @@ pub struct Request {
 impl Request {
     pub fn new(uri: &Uri, using_proxy: bool) -> Request {
         let request_uri = if using_proxy {
-            uri.request_uri().to_string()
+            uri.request_target()
         } else {
             uri.proxy_request_uri()
         };
@@ -29,13 +31,28 @@ impl Request {
             request_uri,
             version: Version::Http11,
             headers: Headers::default_http(&uri.host_header()),
+            header_case: HeaderCase::default(),
+            header_order: Vec::new(),
             host: uri.host_port(),
-            content_len: 0,
             body: None,
             using_proxy,
         }
     }
 
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn using_proxy(&self) -> bool {
+        self.using_proxy
+    }
+
+    // Call uri afterwards to rebuild the request target for the new route.
+    pub fn set_using_proxy(&mut self, using_proxy: bool) -> &mut Self {
+        self.using_proxy = using_proxy;
+        self
+    }
+
     pub fn user_agent(&self) -> Option<String> {
         self.headers.get("User-Agent")
     }
@@ -45,9 +62,7 @@ impl Request {
     }
 
     pub fn headers(&mut self, headers: Headers) -> &mut Self {
-        for (key, value) in headers.iter() {
-            self.headers.insert(key, &value);
-        }
+        self.headers.merge(&headers);
         self
     }
 
@@ -60,6 +75,44 @@ impl Request {
         self
     }
 
+    pub fn header_case(&mut self, header_case: HeaderCase) -> &mut Self {
+        self.header_case = header_case;
+        self
+    }
+
+    pub fn header_order(&mut self, order: Vec<String>) -> &mut Self {
+        self.header_order = order;
+        self
+    }
+
+    pub fn uri(&mut self, uri: &Uri) -> &mut Self {
+        self.request_uri = if self.using_proxy {
+            uri.request_target()
+        } else {
+            uri.proxy_request_uri()
+        };
+        self.host = uri.host_port();
+        self.headers.insert("Host", &uri.host_header());
+        self
+    }
+
+    pub fn get_request_uri(&self) -> &str {
+        &self.request_uri
+    }
+
+    pub fn get_headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn remove_header<T: ToString + ?Sized>(&mut self, key: &T) -> &mut Self {
+        self.headers.remove(key);
+        self
+    }
+
+    pub fn get_method(&self) -> &Method {
+        &self.method
+    }
+
     pub fn method(&mut self, method: Method) -> &mut Self {
         self.method = method;
         self
@@ -83,7 +136,36 @@ impl Request {
         self
     }
 
+    pub fn set_bearer_auth(&mut self, token: &str) -> &mut Self {
+        self.header("Authorization", &format!("Bearer {}", token));
+        self
+    }
+
+    pub fn get_body(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !is_token(self.method.as_str()) {
+            return Err(Error::UnknownMethod(self.method.to_string()));
+        }
+        if self.request_uri.is_empty() || self.request_uri.bytes().any(|b| b <= 0x20 || b == 0x7f) {
+            return Err(Error::InvalidRequestTarget(self.request_uri.clone()));
+        }
+        self.headers.validate()
+    }
+
     pub fn msg(&self) -> Vec<u8> {
+        let mut request_msg = self.msg_head();
+
+        if let Some(b) = &self.body {
+            request_msg.extend(b);
+        }
+
+        request_msg
+    }
+
+    pub fn msg_head(&self) -> Vec<u8> {
         let request_line = format!(
             "{} {} {}{}",
             self.method, self.request_uri, self.version, "\r\n"
@@ -91,16 +173,11 @@ impl Request {
 
         let headers: String = self
             .headers
-            .iter()
-            .map(|(k, v)| format!("{}: {}{}", k, v, "\r\n"))
+            .ordered(&self.header_order)
+            .into_iter()
+            .map(|(k, v)| format!("{}: {}{}", self.header_case.apply(k), v, "\r\n"))
             .collect();
 
-        let mut request_msg = (request_line + &headers + "\r\n").as_bytes().to_vec();
-
-        if let Some(b) = &self.body {
-            request_msg.extend(b);
-        }
-
-        request_msg
+        (request_line + &headers + "\r\n").as_bytes().to_vec()
     }
 }
@@ -4,11 +4,13 @@ use std::str;
 use crate::error::{Error, Result};
 use crate::headers::Headers;
 use crate::status::{Status, StatusCode};
+use crate::uri::Uri;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Response {
     status: Status,
     headers: Headers,
+    redirects: Vec<Uri>,
 }
 
 impl Response {
@@ -16,9 +18,14 @@ impl Response {
         let mut head = str::from_utf8(head)?.splitn(2, '\n');
 
         let status = head.next().ok_or(Error::StatusErr)?.parse()?;
-        let headers = head.next().ok_or(Error::HeadersErr)?.parse()?;
-
-        Ok(Response { status, headers })
+        let lines = head.next().ok_or(Error::HeadersErr)?;
+        let headers = lines.parse()?;
+
+        Ok(Response {
+            status,
+            headers,
+            redirects: Vec::new(),
+        })
     }
 
     pub fn try_from<T: Write>(res: &[u8], writer: &mut T) -> Result<Response> {
@@ -42,17 +49,49 @@ impl Response {
     }
 
     pub fn version(&self) -> &str {
-        &self.status.version()
+        self.status.version()
     }
 
     pub fn reason(&self) -> &str {
-        &self.status.reason()
+        self.status.reason()
     }
 
     pub fn headers(&self) -> &Headers {
         &self.headers
     }
 
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    // Serializes the status line and headers back into a response head.
+    pub fn to_head(&self) -> Vec<u8> {
+        let mut head = format!(
+            "{} {} {}\r\n",
+            self.version(),
+            self.status_code(),
+            self.reason()
+        );
+        for (key, val) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", key, val));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+
+    pub fn cookies(&self) -> Vec<String> {
+        self.headers.get_all("Set-Cookie")
+    }
+
+    pub fn redirects(&self) -> &[Uri] {
+        &self.redirects
+    }
+
+    pub fn set_redirects(&mut self, redirects: Vec<Uri>) -> &mut Self {
+        self.redirects = redirects;
+        self
+    }
+
     pub fn content_len(&self) -> Result<usize> {
         match self.headers().get("Content-Length") {
             Some(p) => Ok(p.parse()?),
@@ -0,0 +1,285 @@
+use std::time::{Duration, SystemTime};
+
+use crate::date::parse_http_date;
+use crate::error::Error;
+use crate::method::Method;
+use crate::random::random_u64;
+use crate::response::Response;
+use crate::uri::{IntoUri, Uri};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    retries: usize,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    non_idempotent: bool,
+    proxies: Vec<Uri>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::new(0)
+    }
+
+    pub fn new(retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            non_idempotent: false,
+            proxies: Vec::new(),
+        }
+    }
+
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.base_delay = delay;
+        self
+    }
+
+    // Upper bound for the backoff. A longer Retry-After isn't waited for, the
+    // response is returned instead.
+    pub fn max_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, enable: bool) -> RetryPolicy {
+        self.jitter = enable;
+        self
+    }
+
+    // Also retries POST, PATCH and other methods that aren't idempotent.
+    pub fn non_idempotent(mut self, enable: bool) -> RetryPolicy {
+        self.non_idempotent = enable;
+        self
+    }
+
+    // Adds a proxy to fail over to. Each retry moves on to the proxy after the
+    // one used last.
+    pub fn proxy<T: IntoUri>(mut self, proxy: T) -> RetryPolicy {
+        if let Ok(proxy) = proxy.into_uri() {
+            self.proxies.push(proxy);
+        }
+        self
+    }
+
+    pub fn next_proxy(&self, current: Option<&Uri>) -> Option<Uri> {
+        let next = match current.and_then(|c| self.proxies.iter().position(|p| p == c)) {
+            Some(i) => (i + 1) % self.proxies.len(),
+            None => 0,
+        };
+        self.proxies.get(next).cloned()
+    }
+
+    // Exponential backoff for the given retry, counted from 1, with equal
+    // jitter: half of the delay is fixed and half is random.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1u32 << retry.saturating_sub(1).min(16);
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let nanos = half.as_nanos() as u64;
+        let random = if nanos == 0 {
+            0
+        } else {
+            random_u64() % (nanos + 1)
+        };
+        half + Duration::from_nanos(random)
+    }
+
+    // Returns the delay before the next attempt, or None when the result
+    // should be returned as is.
+    pub fn delay(
+        &self,
+        result: &Result<Response, Error>,
+        method: &Method,
+        retry: usize,
+    ) -> Option<Duration> {
+        if retry > self.retries {
+            return None;
+        }
+        match result {
+            Err(e) if is_retryable_error(e) && (self.non_idempotent || is_idempotent(method)) => {
+                Some(self.backoff(retry))
+            }
+            Ok(response) => {
+                let code = u16::from(response.status_code());
+                // 429 means the request wasn't processed, so any method is safe.
+                if !is_retryable_status(code)
+                    || (code != 429 && !self.non_idempotent && !is_idempotent(method))
+                {
+                    return None;
+                }
+                match retry_after(response) {
+                    Some(delay) if delay > self.max_delay => None,
+                    Some(delay) => Some(delay),
+                    None => Some(self.backoff(retry)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn is_retryable_error(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Io(_) | Error::RefusedByHost | Error::GeneralFailure | Error::TtlExpired
+    )
+}
+
+pub fn is_retryable_status(code: u16) -> bool {
+    matches!(code, 429 | 502 | 503 | 504)
+}
+
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+// Retry-After holds either seconds or an HTTP-date, RFC 7231, section 7.1.3.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get("Retry-After")?;
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let date = parse_http_date(value)?;
+            Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use std::io;
+
+    fn response(head: &str) -> Result<Response, Error> {
+        Ok(Response::from_head(head.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn retry_delay() {
+        let policy = RetryPolicy::new(2)
+            .base_delay(Duration::from_millis(100))
+            .jitter(false);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        let jittered = RetryPolicy::new(1).backoff(2);
+        assert!(jittered >= Duration::from_millis(100) && jittered <= Duration::from_millis(200));
+
+        let refused: Result<Response, Error> =
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        assert!(policy.delay(&refused, &Method::GET, 1).is_some());
+        assert!(policy.delay(&refused, &Method::POST, 1).is_none());
+        assert!(policy.delay(&refused, &Method::GET, 3).is_none());
+        assert!(policy
+            .delay(&Err(Error::InvalidAuthVersion), &Method::GET, 1)
+            .is_none());
+
+        let busy = response("HTTP/1.1 503 Service Unavailable\r\nRetry-After: 7\r\n\r\n");
+        assert_eq!(
+            policy.delay(&busy, &Method::GET, 1),
+            Some(Duration::from_secs(7))
+        );
+        assert!(policy.delay(&busy, &Method::POST, 1).is_none());
+        let limited = response("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\n\r\n");
+        assert!(policy.delay(&limited, &Method::POST, 1).is_none());
+        let limited = response("HTTP/1.1 429 Too Many Requests\r\n\r\n");
+        assert!(policy.delay(&limited, &Method::POST, 1).is_some());
+        assert!(policy
+            .delay(&response("HTTP/1.1 500 Error\r\n\r\n"), &Method::GET, 1)
+            .is_none());
+    }
+
+    #[test]
+    fn retry_next_proxy() {
+        let policy = RetryPolicy::new(3)
+            .proxy("socks5://127.0.0.1:1080")
+            .proxy("http://127.0.0.1:3128");
+        let first = policy.next_proxy(None).unwrap();
+        assert_eq!(first.scheme(), "socks5");
+        assert_eq!(policy.next_proxy(Some(&first)).unwrap().scheme(), "http");
+        let second = policy.next_proxy(Some(&first)).unwrap();
+        assert_eq!(policy.next_proxy(Some(&second)), Some(first));
+    }
+
+    #[test]
+    fn retry_client() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec(),
+        ]);
+        let mut client = Client::new(uri.as_str())
+            .retry(RetryPolicy::new(3).base_delay(Duration::from_millis(1)))
+            .build()
+            .unwrap();
+        let response = client.send().unwrap();
+        assert!(response.status_code().is_success());
+        assert_eq!(client.text().unwrap(), "ok");
+        assert_eq!(rx.iter().count(), 3);
+    }
+
+    #[test]
+    fn retry_proxy_failover() {
+        let dead = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let (live, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let policy = RetryPolicy::new(2)
+            .base_delay(Duration::from_millis(1))
+            .proxy(dead.as_str())
+            .proxy(live.as_str());
+        let mut client = Client::new("http://example.org/")
+            .proxy(dead.as_str())
+            .retry(policy)
+            .build()
+            .unwrap();
+        client.send().unwrap();
+        assert_eq!(client.proxy().map(|p| p.to_string()), Some(live));
+        assert!(rx.recv().is_ok());
+    }
+
+    #[test]
+    fn retry_direct_to_proxy() {
+        let dead = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/path", listener.local_addr().unwrap())
+        };
+        let (live, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+        ]);
+        let policy = RetryPolicy::new(1)
+            .base_delay(Duration::from_millis(1))
+            .proxy(live.as_str());
+        let mut client = Client::new(dead.as_str()).retry(policy).build().unwrap();
+        assert!(!client.request_mut().using_proxy());
+        client.send().unwrap();
+        assert!(client.request_mut().using_proxy());
+        let request = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(request.starts_with("GET /path HTTP/1.1\r\n"));
+    }
+}
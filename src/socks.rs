@@ -1,4 +1,4 @@
-use std::io::{self, Read, Write};
+use std::io::{Read, Write};
 use std::net::{Ipv4Addr, Ipv6Addr, TcpStream};
 
 use crate::addr::Addr;
@@ -13,7 +13,7 @@ enum AuthMethod {
     Plain = 2,
 }
 
-struct SocksAuth {
+pub(crate) struct SocksAuth {
     method: AuthMethod,
     username: Vec<u8>,
     password: Vec<u8>,
@@ -35,23 +35,38 @@ impl SocksAuth {
             password: Vec::new(),
         }
     }
+
+    // Username and password given in the proxy uri, if both are there.
+    pub fn from_uri(proxy: &Uri) -> Self {
+        match (proxy.authority().username(), proxy.authority().password()) {
+            (Some(username), Some(password)) => SocksAuth::new_plain(username, password),
+            _ => SocksAuth::new(),
+        }
+    }
 }
 
-fn initial_greeting(socket: &mut TcpStream, auth: &SocksAuth) -> io::Result<()> {
+// The packets of the handshake are built and checked here, so that the
+// blocking and the async streams only move bytes.
+
+pub(crate) fn initial_greeting(auth: &SocksAuth) -> [u8; 3] {
     // The initial greeting from the client
     //      field 1: SOCKS version, 1 byte (0x05 for this version)
     //      field 2: number of authentication methods supported, 1 byte
     //      field 3: authentication methods, variable length, 1 byte per method supported
-    socket.write_all(&[5u8, 1u8, auth.method as u8])
+    [5u8, 1u8, auth.method as u8]
 }
 
-fn choise_communicated(socket: &mut TcpStream) -> Result<[u8; 2]> {
+// Returns true when the username and password have to be sent.
+pub(crate) fn choise_communicated(buf: [u8; 2], auth: &SocksAuth) -> Result<bool> {
     // The server's choice is communicated:
     //      field 1: SOCKS version, 1 byte (0x05 for this version)
     //      field 2: chosen authentication method, 1 byte, or 0xFF if no acceptable methods were offered
-    let mut buf = [0u8; 2];
-    socket.read_exact(&mut buf)?;
-    Ok(buf)
+    is_valid_socks_version(buf[0])?;
+    if buf[1] != auth.method as u8 {
+        Err(Error::InvalidAuthMethod)
+    } else {
+        Ok(buf[1] == AuthMethod::Plain as u8)
+    }
 }
 
 fn is_valid_socks_version(value: u8) -> Result<()> {
@@ -61,40 +76,35 @@ fn is_valid_socks_version(value: u8) -> Result<()> {
     }
 }
 
-fn try_auth(socket: &mut TcpStream, value: u8, auth: &SocksAuth) -> Result<()> {
-    if value == auth.method as u8 && value == 2u8 {
-        // For username/password authentication the client's authentication request is
-        //     field 1: version number, 1 byte (0x01 for current version of username/password authentication)
-        let mut packet = vec![1u8];
-        //     field 2: username length, 1 byte
-        packet.push(auth.username.len() as u8);
-        //     field 3: username, 1–255 bytes
-        packet.append(&mut auth.username.clone());
-        //     field 4: password length, 1 byte
-        packet.push(auth.password.len() as u8);
-        //     field 5: password, 1–255 bytes
-        packet.append(&mut auth.password.clone());
-        socket.write_all(&packet)?;
-        let mut buf = [0u8; 2];
-        socket.read_exact(&mut buf)?;
-        // Server response for username/password authentication:
-        //     field 1: version, 1 byte (0x01 for current version of username/password authentication)
-        //     field 2: status code, 1 byte
-        //         0x00: success
-        //         any other value is a failure, connection must be closed
-        match (buf[0] != 1u8, buf[1] != 0u8) {
-            (true, _) => Err(Error::InvalidAuthVersion),
-            (_, true) => Err(Error::AuthFailure),
-            _ => Ok(()),
-        }
-    } else if value == auth.method as u8 {
-        Ok(())
-    } else {
-        Err(Error::InvalidAuthMethod)
+pub(crate) fn auth_request(auth: &SocksAuth) -> Vec<u8> {
+    // For username/password authentication the client's authentication request is
+    //     field 1: version number, 1 byte (0x01 for current version of username/password authentication)
+    let mut packet = vec![1u8];
+    //     field 2: username length, 1 byte
+    packet.push(auth.username.len() as u8);
+    //     field 3: username, 1–255 bytes
+    packet.extend(&auth.username);
+    //     field 4: password length, 1 byte
+    packet.push(auth.password.len() as u8);
+    //     field 5: password, 1–255 bytes
+    packet.extend(&auth.password);
+    packet
+}
+
+pub(crate) fn auth_response(buf: [u8; 2]) -> Result<()> {
+    // Server response for username/password authentication:
+    //     field 1: version, 1 byte (0x01 for current version of username/password authentication)
+    //     field 2: status code, 1 byte
+    //         0x00: success
+    //         any other value is a failure, connection must be closed
+    match (buf[0] != 1u8, buf[1] != 0u8) {
+        (true, _) => Err(Error::InvalidAuthVersion),
+        (_, true) => Err(Error::AuthFailure),
+        _ => Ok(()),
     }
 }
 
-fn request_connection(socket: &mut TcpStream, target: Vec<u8>) -> io::Result<()> {
+pub(crate) fn request_connection(target: &Uri) -> Vec<u8> {
     let mut packet = Vec::new();
     // The client's connection request is
     //     field 1: SOCKS version number, 1 byte (0x05 for this version)
@@ -115,13 +125,11 @@ fn request_connection(socket: &mut TcpStream, target: Vec<u8>) -> io::Result<()>
     //         1 byte of name length followed by 1–255 bytes the domain name
     //         16 bytes for IPv6 address
     //     field 6: port number in a network byte order, 2 bytes
-    packet.append(&mut target.clone());
-    socket.write_all(&packet)
+    packet.append(&mut target.to_vec());
+    packet
 }
 
-fn get_server_reponse(socket: &mut TcpStream) -> Result<()> {
-    let mut buf = [0u8; 3];
-    socket.read_exact(&mut buf)?;
+pub(crate) fn get_server_reponse(buf: [u8; 3]) -> Result<()> {
     // Server response:
     //     field 1: SOCKS protocol version, 1 byte (0x05 for this version)
     is_valid_socks_version(buf[0])?;
@@ -155,37 +163,51 @@ fn get_server_reponse(socket: &mut TcpStream) -> Result<()> {
     }
 }
 
-fn get_host(socket: &mut TcpStream) -> Result<Addr> {
-    let mut buf = [0u8; 1];
+// Length of the bound address for an address type, None for a domain name,
+// whose length comes in the next byte.
+pub(crate) fn host_len(address_type: u8) -> Result<Option<usize>> {
     //     field 4: address type, 1 byte:
     //         0x01: IPv4 address
     //         0x03: Domain name
     //         0x04: IPv6 address
+    match address_type {
+        1 => Ok(Some(4)),
+        3 => Ok(None),
+        4 => Ok(Some(16)),
+        _ => Err(Error::InvalidAddressType),
+    }
+}
+
+fn get_host(address_type: u8, buf: &[u8]) -> Addr {
     //     field 5: server bound address of
     //         4 bytes for IPv4 address
     //         1 byte of name length followed by 1–255 bytes the domain name
     //         16 bytes for IPv6 address
-    socket.read_exact(&mut buf)?;
-    match buf[0] {
-        1 => {
-            let mut buf = [0u8; 4];
-            socket.read_exact(&mut buf)?;
-            Ok(Addr::Ipv4(Ipv4Addr::from(buf)))
+    match (address_type, buf.len()) {
+        (1, 4) => Addr::Ipv4(Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3])),
+        (4, 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(buf);
+            Addr::Ipv6(Ipv6Addr::from(octets))
         }
-        3 => {
+        _ => Addr::Domain(String::from_utf8_lossy(buf).into_owned()),
+    }
+}
+
+fn read_host(socket: &mut TcpStream) -> Result<Addr> {
+    let mut address_type = [0u8; 1];
+    socket.read_exact(&mut address_type)?;
+    let len = match host_len(address_type[0])? {
+        Some(len) => len,
+        None => {
             let mut len = [0u8; 1];
             socket.read_exact(&mut len)?;
-            let mut buf = vec![0u8; len[0] as usize];
-            socket.read_exact(&mut buf)?;
-            Ok(Addr::Domain(String::from_utf8_lossy(&buf).into_owned()))
+            len[0] as usize
         }
-        4 => {
-            let mut buf = [0u8; 16];
-            socket.read_exact(&mut buf)?;
-            Ok(Addr::Ipv6(Ipv6Addr::from(buf)))
-        }
-        _ => Err(Error::InvalidAddressType),
-    }
+    };
+    let mut buf = vec![0u8; len];
+    socket.read_exact(&mut buf)?;
+    Ok(get_host(address_type[0], &buf))
 }
 
 fn get_port(socket: &mut TcpStream) -> Result<[u8; 2]> {
@@ -203,12 +225,7 @@ pub struct SocksStream {
 
 impl SocksStream {
     pub fn connect(proxy: &Uri, target: &Uri) -> Result<SocksStream> {
-        match (proxy.authority().username(), proxy.authority().password()) {
-            (Some(username), Some(password)) => {
-                Self::connect_plain(proxy, target, username, password)
-            }
-            _ => Self::handshake(proxy, target, &SocksAuth::new()),
-        }
+        Self::handshake(proxy, target, &SocksAuth::from_uri(proxy))
     }
 
     pub fn connect_plain(
@@ -223,16 +240,22 @@ impl SocksStream {
     fn handshake(proxy: &Uri, target: &Uri, auth: &SocksAuth) -> Result<SocksStream> {
         let proxy_addr = proxy.socket_addr()?;
         let mut socket = TcpStream::connect(proxy_addr)?;
-        initial_greeting(&mut socket, auth)?;
-        let buf = choise_communicated(&mut socket)?;
-        is_valid_socks_version(buf[0])?;
-        try_auth(&mut socket, buf[1], auth)?;
-        request_connection(&mut socket, target.to_vec())?;
-        get_server_reponse(&mut socket)?;
-        let _host = get_host(&mut socket)?;
+        socket.write_all(&initial_greeting(auth))?;
+        let mut buf = [0u8; 2];
+        socket.read_exact(&mut buf)?;
+        if choise_communicated(buf, auth)? {
+            socket.write_all(&auth_request(auth))?;
+            socket.read_exact(&mut buf)?;
+            auth_response(buf)?;
+        }
+        socket.write_all(&request_connection(target))?;
+        let mut buf = [0u8; 3];
+        socket.read_exact(&mut buf)?;
+        get_server_reponse(buf)?;
+        let _host = read_host(&mut socket)?;
         let _port = get_port(&mut socket)?;
         let stream = if target.is_ssl() {
-            Stream::new_tls(&target.host(), socket)?
+            Stream::new_tls(target.host(), socket)?
         } else {
             Stream::new_tcp(socket)
         };
@@ -243,6 +266,14 @@ impl SocksStream {
         })
     }
 
+    pub fn target(&self) -> &Addr {
+        &self.target
+    }
+
+    pub fn stream_mut(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+
     pub fn send_request(&mut self, req: &[u8]) -> Result<()> {
         Stream::send_msg(&mut self.stream, req)
     }
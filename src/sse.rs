@@ -0,0 +1,404 @@
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
+
+use crate::client::Client;
+use crate::encoding::Decoder;
+use crate::error::{Error, Result};
+
+// Longest line accepted, so a stream without line breaks can't grow memory
+// without bound.
+pub const MAX_LINE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+    pub retry: Option<Duration>,
+}
+
+// Parses a text/event-stream as described in the HTML Living Standard,
+// section 9.2.6.
+#[derive(Debug)]
+pub struct EventReader<R> {
+    reader: R,
+    last_event_id: Option<String>,
+    // Reconnection time, set as soon as a retry field is read.
+    retry: Option<Duration>,
+    skip_lf: bool,
+    started: bool,
+}
+
+impl<R: BufRead> EventReader<R> {
+    pub fn new(reader: R) -> EventReader<R> {
+        EventReader {
+            reader,
+            last_event_id: None,
+            retry: None,
+            skip_lf: false,
+            started: false,
+        }
+    }
+
+    // The id a reconnect continues from; it carries over to later events.
+    pub fn last_event_id(mut self, id: Option<String>) -> EventReader<R> {
+        self.last_event_id = id;
+        self
+    }
+
+    pub fn get_last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub fn get_retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    // Returns None at the end of the stream, discarding an unfinished event.
+    pub fn next_event(&mut self) -> Result<Option<Event>> {
+        let mut event = String::new();
+        let mut data = String::new();
+        let mut retry = None;
+        while let Some(line) = self.read_line()? {
+            if line.is_empty() {
+                if data.is_empty() {
+                    event.clear();
+                    retry = None;
+                    continue;
+                }
+                data.pop();
+                return Ok(Some(Event {
+                    event: if event.is_empty() {
+                        "message".to_string()
+                    } else {
+                        event
+                    },
+                    data,
+                    id: self.last_event_id.clone(),
+                    retry,
+                }));
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line.as_str(), ""),
+            };
+            match field {
+                "event" => event = value.to_string(),
+                "data" => {
+                    data.push_str(value);
+                    data.push('\n');
+                }
+                // An empty id resets it, so no Last-Event-ID is sent.
+                "id" if value.is_empty() => self.last_event_id = None,
+                "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+                "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    if let Ok(ms) = value.parse() {
+                        retry = Some(Duration::from_millis(ms));
+                        self.retry = retry;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(None)
+    }
+
+    // Lines end with CRLF, LF or a lone CR.
+    fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = Vec::new();
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            if self.skip_lf {
+                self.skip_lf = false;
+                if buf[0] == b'\n' {
+                    self.reader.consume(1);
+                    continue;
+                }
+            }
+            let end = match buf.iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(pos) => {
+                    line.extend(&buf[..pos]);
+                    self.skip_lf = buf[pos] == b'\r';
+                    self.reader.consume(pos + 1);
+                    true
+                }
+                None => {
+                    let len = buf.len();
+                    line.extend(buf);
+                    self.reader.consume(len);
+                    false
+                }
+            };
+            if line.len() > MAX_LINE {
+                return Err(Error::LineTooLong(MAX_LINE));
+            }
+            if end {
+                break;
+            }
+        }
+        let mut line = String::from_utf8_lossy(&line).into_owned();
+        if !self.started {
+            self.started = true;
+            if line.starts_with('\u{feff}') {
+                line.remove(0);
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+impl<R: BufRead> Iterator for EventReader<R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+// Reads events from a client and reconnects when the stream ends or breaks,
+// sending the id of the last event seen in Last-Event-ID.
+pub struct EventSource {
+    client: Client,
+    reader: Option<EventReader<BufReader<Decoder>>>,
+    last_event_id: Option<String>,
+    retry: Duration,
+    retries: usize,
+    attempts: usize,
+    connected: bool,
+    done: bool,
+}
+
+impl EventSource {
+    pub fn new(client: Client) -> EventSource {
+        EventSource {
+            client,
+            reader: None,
+            last_event_id: None,
+            retry: Duration::from_secs(3),
+            retries: 3,
+            attempts: 0,
+            connected: false,
+            done: false,
+        }
+    }
+
+    // Reconnect attempts in a row before giving up.
+    pub fn retries(mut self, retries: usize) -> EventSource {
+        self.retries = retries;
+        self
+    }
+
+    // Initial reconnection delay, until the server sends a retry field.
+    pub fn retry(mut self, delay: Duration) -> EventSource {
+        self.retry = delay;
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn get_last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    // Returns Ok(false) when the server answered 204 to stop the stream.
+    fn connect(&mut self) -> Result<bool> {
+        if self.connected {
+            self.client.reconnect()?;
+        }
+        self.connected = true;
+        let request = self.client.request_mut();
+        request.header("Accept", "text/event-stream");
+        request.header("Cache-Control", "no-cache");
+        match &self.last_event_id {
+            Some(id) => request.header("Last-Event-ID", id),
+            None => request.remove_header("Last-Event-ID"),
+        };
+        let response = self.client.send()?;
+        match u16::from(response.status_code()) {
+            200 => (),
+            204 => return Ok(false),
+            _ => return Err(Error::UnexpectedStatus(response.status_code())),
+        }
+        let content_type = response.headers().get("Content-Type").unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        if !mime.eq_ignore_ascii_case("text/event-stream") {
+            return Err(Error::UnexpectedContentType(content_type));
+        }
+        let reader = BufReader::new(self.client.body_reader()?);
+        self.reader = Some(EventReader::new(reader).last_event_id(self.last_event_id.clone()));
+        Ok(true)
+    }
+}
+
+impl fmt::Debug for EventSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventSource")
+            .field("client", &self.client)
+            .field("last_event_id", &self.last_event_id)
+            .field("retry", &self.retry)
+            .field("retries", &self.retries)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl Iterator for EventSource {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+            let error = match &mut self.reader {
+                Some(reader) => {
+                    let result = reader.next_event();
+                    // Blocks without data still set the id and the delay.
+                    self.last_event_id = reader.get_last_event_id().map(str::to_string);
+                    if let Some(retry) = reader.get_retry() {
+                        self.retry = retry;
+                    }
+                    match result {
+                        Ok(Some(event)) => {
+                            self.attempts = 0;
+                            return Some(Ok(event));
+                        }
+                        Ok(None) => None,
+                        Err(e) => Some(e),
+                    }
+                }
+                None => match self.connect() {
+                    Ok(true) => continue,
+                    Ok(false) => {
+                        self.done = true;
+                        return None;
+                    }
+                    Err(e @ Error::Io(_)) => Some(e),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                },
+            };
+            self.reader = None;
+            if self.attempts >= self.retries {
+                self.done = true;
+                return error.map(Err);
+            }
+            self.attempts += 1;
+            thread::sleep(self.retry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_parse() {
+        let stream = "\u{feff}: comment\r\nretry: 250\r\nid: 7\r\nevent: update\r\n\
+                      data: first\rdata:second\n\ndata\n\nid\ndata: x\n\n\ndata: dropped";
+        let events: Vec<Event> = EventReader::new(stream.as_bytes())
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    event: "update".to_string(),
+                    data: "first\nsecond".to_string(),
+                    id: Some("7".to_string()),
+                    retry: Some(Duration::from_millis(250)),
+                },
+                Event {
+                    event: "message".to_string(),
+                    data: String::new(),
+                    id: Some("7".to_string()),
+                    retry: None,
+                },
+                Event {
+                    event: "message".to_string(),
+                    data: "x".to_string(),
+                    id: None,
+                    retry: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sse_fields_without_data() {
+        let mut reader = EventReader::new(&b"retry: 5000\n\nid: 3\n\ndata: x\n\n"[..]);
+        let event = reader.next_event().unwrap().unwrap();
+        assert_eq!(event.id, Some("3".to_string()));
+        assert_eq!(event.retry, None);
+        assert_eq!(reader.get_retry(), Some(Duration::from_millis(5000)));
+        assert_eq!(reader.get_last_event_id(), Some("3"));
+    }
+
+    #[test]
+    fn sse_long_line() {
+        let stream = vec![b'a'; MAX_LINE + 1];
+        let mut reader = EventReader::new(&stream[..]);
+        assert!(matches!(
+            reader.next_event(),
+            Err(Error::LineTooLong(MAX_LINE))
+        ));
+    }
+
+    #[test]
+    fn sse_reconnect() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n\
+              retry: 10\nid: 1\ndata: hello\n\n"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream; charset=utf-8\r\n\r\n\
+              id: 2\ndata: again\n\n"
+                .to_vec(),
+            b"HTTP/1.1 204 No Content\r\n\r\n".to_vec(),
+        ]);
+        let client = Client::new(uri.as_str()).build().unwrap();
+        let events: Vec<Event> = EventSource::new(client).map(|e| e.unwrap()).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, "hello");
+        assert_eq!(events[1].data, "again");
+        assert_eq!(events[1].id, Some("2".to_string()));
+
+        let first = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(first.contains("Accept: text/event-stream\r\n"));
+        assert!(!first.contains("Last-Event-ID"));
+        let second = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(second.contains("Last-Event-ID: 1\r\n"));
+        let third = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(third.contains("Last-Event-ID: 2\r\n"));
+    }
+
+    #[test]
+    fn sse_reconnect_without_data() {
+        let (uri, rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n\
+              retry: 10\n\nid: 5\n\n"
+                .to_vec(),
+            b"HTTP/1.1 204 No Content\r\n\r\n".to_vec(),
+        ]);
+        let client = Client::new(uri.as_str()).build().unwrap();
+        let mut source = EventSource::new(client).retry(Duration::from_secs(60));
+        assert!(source.next().is_none());
+        assert_eq!(source.get_last_event_id(), Some("5"));
+        assert_eq!(source.retry, Duration::from_millis(10));
+        rx.recv().unwrap();
+        let second = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(second.contains("Last-Event-ID: 5\r\n"));
+    }
+}
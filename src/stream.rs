@@ -1,9 +1,11 @@
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
+use std::time::Duration;
 
 use native_tls::{TlsConnector, TlsStream};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::limits::{read_head, Limits};
 use crate::response::Response;
 
 #[derive(Debug)]
@@ -22,6 +24,14 @@ impl Stream {
         Ok(Stream::Tls(Box::new(builder.connect(domain, stream)?)))
     }
 
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout)?,
+            Stream::Tls(stream) => stream.get_ref().set_read_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
     pub fn send_msg(stream: &mut Stream, msg: &[u8]) -> Result<()> {
         stream.write_all(msg)?;
         stream.flush()?;
@@ -29,14 +39,34 @@ impl Stream {
     }
 
     pub fn read_head(stream: &mut Stream) -> Result<Response> {
-        let mut head = Vec::with_capacity(200);
-        copy_until(stream, &mut head, &[13, 10, 13, 10])?;
-        Response::from_head(&head)
+        Stream::read_head_with(stream, &Limits::default())
+    }
+
+    pub fn read_head_with(stream: &mut Stream, limits: &Limits) -> Result<Response> {
+        Response::from_head(&read_head(stream, limits)?)
     }
 
     pub fn get_body(stream: &mut Stream, content_len: usize) -> Result<Vec<u8>> {
-        let mut body = vec![0u8; content_len];
-        stream.read_exact(&mut body)?;
+        Stream::get_body_with(stream, content_len, &Limits::default())
+    }
+
+    // The buffer grows with the data that arrives, not with the length the
+    // server claims.
+    pub fn get_body_with(
+        stream: &mut Stream,
+        content_len: usize,
+        limits: &Limits,
+    ) -> Result<Vec<u8>> {
+        if let Some(max) = limits.body_bytes {
+            if content_len as u64 > max {
+                return Err(Error::BodyTooLarge(max));
+            }
+        }
+        let mut body = Vec::new();
+        stream.take(content_len as u64).read_to_end(&mut body)?;
+        if body.len() < content_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
         Ok(body)
     }
 }
@@ -66,24 +96,31 @@ impl Write for Stream {
     }
 }
 
+// Stops with Error::HeadersTooLarge after the default header_bytes limit.
+#[deprecated(note = "use limits::read_head, which checks all of the limits")]
 pub fn copy_until<R, W>(reader: &mut R, writer: &mut W, val: &[u8]) -> Result<usize>
 where
     R: Read + ?Sized,
     W: Write + ?Sized,
 {
+    let max = Limits::default().header_bytes;
     let mut buf = Vec::with_capacity(200);
 
     let mut pre_buf = [0; 10];
     let mut read = reader.read(&mut pre_buf)?;
     buf.extend(&pre_buf[..read]);
 
-    for byte in reader.bytes() {
-        buf.push(byte?);
+    let mut byte = [0u8; 1];
+    while reader.read(&mut byte)? == 1 {
+        buf.push(byte[0]);
         read += 1;
 
-        if &buf[(buf.len() - val.len())..] == val {
+        if buf.ends_with(val) {
             break;
         }
+        if read > max {
+            return Err(Error::HeadersTooLarge(max));
+        }
     }
 
     writer.write_all(&buf)?;
@@ -92,28 +129,48 @@ where
     Ok(read)
 }
 
-// pub fn copy_until_len<R, W>(reader: &mut R, writer: &mut W, len: usize) -> Result<usize>
-// where
-//     R: Read + ?Sized,
-//     W: Write + ?Sized,
-// {
-//     let mut buf = Vec::with_capacity(len);
-
-//     let mut pre_buf = [0; 10];
-//     let mut read = reader.read(&mut pre_buf)?;
-//     buf.extend(&pre_buf[..read]);
-
-//     for (i, byte) in reader.bytes().enumerate() {
-//         buf.push(byte?);
-//         read += 1;
-
-//         if i == len + 1 {
-//             break;
-//         }
-//     }
-
-//     writer.write_all(&buf)?;
-//     writer.flush()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+
+    #[test]
+    fn stream_body_limit() {
+        let (uri, _rx) = crate::tests::serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_vec(),
+        ]);
+        let uri = uri.parse::<crate::uri::Uri>().unwrap();
+        let mut stream = Stream::new_tcp(TcpStream::connect(uri.host_port()).unwrap());
+        Stream::send_msg(&mut stream, b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        Stream::read_head(&mut stream).unwrap();
+        let limits = Limits {
+            body_bytes: Some(4),
+            ..Limits::default()
+        };
+        assert!(matches!(
+            Stream::get_body_with(&mut stream, 5, &limits),
+            Err(Error::BodyTooLarge(4))
+        ));
+        assert_eq!(Stream::get_body(&mut stream, 5).unwrap(), b"hello");
+        assert!(Stream::get_body(&mut stream, usize::MAX).is_err());
+    }
 
-//     Ok(read)
-// }
+    #[test]
+    #[allow(deprecated)]
+    fn stream_copy_until() {
+        let mut out = Vec::new();
+        let read = copy_until(
+            &mut &b"HTTP/1.1 200 OK\r\n\r\nbody"[..],
+            &mut out,
+            b"\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(read, 19);
+        assert_eq!(out, b"HTTP/1.1 200 OK\r\n\r\n");
+        let endless = vec![b'a'; 70 * 1024];
+        assert!(matches!(
+            copy_until(&mut &endless[..], &mut Vec::new(), b"\r\n\r\n"),
+            Err(Error::HeadersTooLarge(_))
+        ));
+    }
+}
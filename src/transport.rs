@@ -1,21 +1,17 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::http::HttpStream;
 use crate::proxy::Proxy;
+use crate::stream::Stream;
 use crate::uri::Uri;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum Transport {
     Proxy(Proxy),
     Stream(HttpStream),
+    #[default]
     None,
 }
 
-impl Default for Transport {
-    fn default() -> Self {
-        Transport::None
-    }
-}
-
 impl Transport {
     pub fn new() -> Self {
         Transport::default()
@@ -28,4 +24,12 @@ impl Transport {
     pub fn stream(uri: &Uri) -> Result<Self> {
         Ok(Transport::Stream(HttpStream::connect(uri)?))
     }
+
+    pub fn stream_mut(&mut self) -> Result<&mut Stream> {
+        match self {
+            Transport::Proxy(proxy) => Ok(proxy.stream_mut()),
+            Transport::Stream(stream) => Ok(stream.stream_mut()),
+            Transport::None => Err(Error::WrongHttp),
+        }
+    }
 }
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::callback::Callback;
+use crate::error::Result;
+use crate::progress::{Progress, ProgressReader};
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+pub struct Upload {
+    reader: Box<dyn Read + Send>,
+    len: Option<u64>,
+}
+
+impl Upload {
+    pub fn new<R: Read + Send + 'static>(reader: R, len: Option<u64>) -> Upload {
+        Upload {
+            reader: Box::new(reader),
+            len,
+        }
+    }
+
+    pub fn content_len(&self) -> Option<u64> {
+        self.len
+    }
+
+    pub fn progress(self, callback: Callback<Progress>) -> Upload {
+        Upload {
+            reader: Box::new(ProgressReader::new(self.reader, callback, self.len)),
+            len: self.len,
+        }
+    }
+
+    // A known length is sent as is, anything else with chunked transfer coding.
+    pub fn write_to<W: Write + ?Sized>(mut self, stream: &mut W) -> Result<u64> {
+        let written = match self.len {
+            Some(len) => {
+                let written = io::copy(&mut self.reader.take(len), stream)?;
+                if written < len {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                written
+            }
+            None => write_chunked(&mut self.reader, stream)?,
+        };
+        stream.flush()?;
+        Ok(written)
+    }
+}
+
+impl fmt::Debug for Upload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Upload").field("len", &self.len).finish()
+    }
+}
+
+fn write_chunked<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(format!("{:x}\r\n", read).as_bytes())?;
+        writer.write_all(&buf[..read])?;
+        writer.write_all(b"\r\n")?;
+        written += read as u64;
+    }
+    writer.write_all(b"0\r\n\r\n")?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_chunked() {
+        let mut out = Vec::new();
+        let written = write_chunked(&mut &b"hello"[..], &mut out).unwrap();
+        assert_eq!(written, 5);
+        assert_eq!(out, b"5\r\nhello\r\n0\r\n\r\n");
+    }
+}
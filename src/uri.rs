@@ -20,7 +20,7 @@ impl IntoUri for Uri {
     }
 }
 
-impl<'a> IntoUri for &'a str {
+impl IntoUri for &str {
     fn into_uri(self) -> Result<Uri> {
         self.parse()
     }
@@ -79,8 +79,8 @@ impl Uri {
 
     pub fn default_port(&self) -> u16 {
         let default_port = match self.scheme() {
-            "https" => 443,
-            "http" => 80,
+            "https" | "wss" => 443,
+            "http" | "ws" => 80,
             "socks5" | "socks5h" => 1080,
             _ => 80,
         };
@@ -106,27 +106,95 @@ impl Uri {
     pub fn request_uri(&self) -> &str {
         let mut result = "/";
 
-        for v in &[self.path, self.query, self.fragment] {
-            if let Some(r) = v {
-                result = &self.inner[r.start..];
-                break;
-            }
+        if let Some(r) = [self.path, self.query, self.fragment]
+            .iter()
+            .flatten()
+            .next()
+        {
+            result = &self.inner[r.start..];
         }
 
         result
     }
 
+    // The path and query to put in a request line, without the fragment and
+    // with "/" for an empty path.
+    pub fn request_target(&self) -> String {
+        let path = self.path().filter(|p| !p.is_empty()).unwrap_or("/");
+        match self.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string(),
+        }
+    }
+
     pub fn proxy_request_uri(&self) -> String {
-        let mut result = "/";
+        format!(
+            "{}://{}{}",
+            self.scheme(),
+            self.host_port(),
+            self.request_target()
+        )
+    }
 
-        for v in &[self.path, self.query, self.fragment] {
-            if let Some(r) = v {
-                result = &self.inner[r.start..];
-                break;
-            }
+    pub fn append_query(&self, query: &str) -> Result<Uri> {
+        if query.is_empty() {
+            return Ok(self.clone());
         }
+        let mut uri = self.without_fragment();
+        match self.query() {
+            None => uri.push('?'),
+            Some(q) if q.is_empty() || q.ends_with('&') => (),
+            Some(_) => uri.push('&'),
+        }
+        uri.push_str(query);
+        if let Some(fragment) = self.fragment() {
+            uri.push('#');
+            uri.push_str(fragment);
+        }
+        uri.parse()
+    }
 
-        format!("{}://{}{}", self.scheme(), self.host_port(), result)
+    pub fn without_fragment(&self) -> String {
+        match self.fragment {
+            Some(r) => self.inner[..r.start - 1].to_string(),
+            None => self.inner.clone(),
+        }
+    }
+
+    pub fn join(&self, reference: &str) -> Result<Uri> {
+        let reference = reference.trim();
+        if has_scheme(reference) {
+            return reference.parse();
+        }
+        if reference.starts_with("//") {
+            return format!("{}:{}", self.scheme(), reference).parse();
+        }
+
+        let base = match self.port() {
+            Some(port) => format!("{}://{}:{}", self.scheme(), self.host(), port),
+            None => format!("{}://{}", self.scheme(), self.host()),
+        };
+        let path = self.path().unwrap_or("/");
+        let query = self.query().map(|q| format!("?{}", q)).unwrap_or_default();
+
+        let target = match reference.chars().next() {
+            None => format!("{}{}{}", base, path, query),
+            Some('#') => format!("{}{}{}{}", base, path, query, reference),
+            Some('?') => format!("{}{}{}", base, path, reference),
+            Some('/') => format!("{}{}", base, remove_dot_segments(reference)),
+            Some(_) => {
+                let dir = match path.rfind('/') {
+                    Some(i) => &path[..=i],
+                    None => "/",
+                };
+                format!(
+                    "{}{}",
+                    base,
+                    remove_dot_segments(&format!("{}{}", dir, reference))
+                )
+            }
+        };
+        target.parse()
     }
 
     pub fn origin(&self) -> String {
@@ -146,7 +214,7 @@ impl Uri {
     }
 
     pub fn is_ssl(&self) -> bool {
-        self.scheme() == "https"
+        matches!(self.scheme(), "https" | "wss")
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
@@ -234,7 +302,10 @@ impl FromStr for Uri {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let mut s = s.to_string();
+        let mut s = s.trim().to_string();
+        if s.chars().any(|c| c.is_control()) {
+            return Err(Error::InvalidRequestTarget(s));
+        }
         remove_spaces(&mut s);
 
         let (uri_part, fragment) =
@@ -295,8 +366,49 @@ fn remove_spaces(text: &mut String) {
     text.retain(|c| !c.is_whitespace());
 }
 
+fn has_scheme(s: &str) -> bool {
+    match s.find(':') {
+        Some(i) => {
+            let scheme = &s[..i];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+fn remove_dot_segments(reference: &str) -> String {
+    let end = reference.find(['?', '#']).unwrap_or(reference.len());
+    let (path, rest) = reference.split_at(end);
+
+    let mut output: Vec<&str> = Vec::new();
+    let segments: Vec<&str> = path.split('/').collect();
+    for (i, segment) in segments.iter().enumerate().skip(1) {
+        let last = i == segments.len() - 1;
+        match *segment {
+            "." => {
+                if last {
+                    output.push("");
+                }
+            }
+            ".." => {
+                output.pop();
+                if last {
+                    output.push("");
+                }
+            }
+            s => output.push(s),
+        }
+    }
+
+    format!("/{}{}", output.join("/"), rest)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::uri::Uri;
 
     #[test]
@@ -327,6 +439,33 @@ mod tests {
         assert_eq!(uri.request_uri(), "/foo%2fbar/baz%2Fquux?alt=media");
     }
 
+    #[test]
+    fn request_target_without_path() {
+        let uri = "http://www.example.org?a=1#top".parse::<Uri>().unwrap();
+        assert_eq!(uri.request_target(), "/?a=1");
+        assert_eq!(uri.proxy_request_uri(), "http://www.example.org:80/?a=1");
+    }
+
+    #[test]
+    fn append_query() {
+        let uri = "http://www.example.org/p#top".parse::<Uri>().unwrap();
+        let uri = uri.append_query("a=1").unwrap();
+        assert_eq!(uri.as_str(), "http://www.example.org/p?a=1#top");
+        let uri = uri.append_query("b=%20").unwrap();
+        assert_eq!(uri.query(), Some("a=1&b=%20"));
+        assert_eq!(uri.fragment(), Some("top"));
+    }
+
+    #[test]
+    fn control_chars() {
+        assert!(" http://www.example.org/\n".parse::<Uri>().is_ok());
+        assert!(matches!(
+            "http://www.example.org/a\r\nHost: evil".parse::<Uri>(),
+            Err(Error::InvalidRequestTarget(_))
+        ));
+        assert!("http://www.example.org/\0".parse::<Uri>().is_err());
+    }
+
     #[test]
     fn no_path() {
         let uri = "http://www.example.org".parse::<Uri>().unwrap();
@@ -638,6 +777,41 @@ mod tests {
     //      		u.path = Some("/foo");
     //          // }
 
+    #[test]
+    fn join_absolute() {
+        let uri = "http://a/b/c/d;p?q".parse::<Uri>().unwrap();
+        assert_eq!(uri.join("https://h/x").unwrap().as_str(), "https://h/x");
+        assert_eq!(uri.join("//g").unwrap().as_str(), "http://g");
+    }
+
+    #[test]
+    fn join_relative() {
+        let uri = "http://a/b/c/d;p?q".parse::<Uri>().unwrap();
+        assert_eq!(uri.join("g").unwrap().as_str(), "http://a/b/c/g");
+        assert_eq!(uri.join("./g").unwrap().as_str(), "http://a/b/c/g");
+        assert_eq!(uri.join("g/").unwrap().as_str(), "http://a/b/c/g/");
+        assert_eq!(uri.join("/g").unwrap().as_str(), "http://a/g");
+        assert_eq!(uri.join("?y").unwrap().as_str(), "http://a/b/c/d;p?y");
+        assert_eq!(uri.join("g?y").unwrap().as_str(), "http://a/b/c/g?y");
+        assert_eq!(uri.join("#s").unwrap().as_str(), "http://a/b/c/d;p?q#s");
+        assert_eq!(uri.join("..").unwrap().as_str(), "http://a/b/");
+        assert_eq!(uri.join("../g").unwrap().as_str(), "http://a/b/g");
+        assert_eq!(uri.join("../../../g").unwrap().as_str(), "http://a/g");
+        assert_eq!(uri.join("").unwrap().as_str(), "http://a/b/c/d;p?q");
+    }
+
+    #[test]
+    fn join_port() {
+        let uri = "http://a:8000/b".parse::<Uri>().unwrap();
+        assert_eq!(uri.join("/c").unwrap().as_str(), "http://a:8000/c");
+    }
+
+    #[test]
+    fn without_fragment() {
+        let uri = "http://a/b?c#d".parse::<Uri>().unwrap();
+        assert_eq!(uri.without_fragment(), "http://a/b?c");
+    }
+
     #[test]
     fn example4() {
         let uri = "http://example.com//foo".parse::<Uri>().unwrap();
@@ -4,9 +4,9 @@ pub struct UserInfo {
     pub password: String,
 }
 
-impl Into<UserInfo> for &str {
-    fn into(self) -> UserInfo {
-        let split: Vec<&str> = self.splitn(2, ':').collect();
+impl From<&str> for UserInfo {
+    fn from(s: &str) -> UserInfo {
+        let split: Vec<&str> = s.splitn(2, ':').collect();
         let (username, password) = if split.len() == 2 {
             (split[0].to_owned(), split[1].to_owned())
         } else {
@@ -16,8 +16,8 @@ impl Into<UserInfo> for &str {
     }
 }
 
-impl Into<UserInfo> for String {
-    fn into(self) -> UserInfo {
-        self.as_str().into()
+impl From<String> for UserInfo {
+    fn from(s: String) -> UserInfo {
+        s.as_str().into()
     }
 }
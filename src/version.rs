@@ -3,10 +3,11 @@ use std::str::FromStr;
 
 use crate::error::{Error, Result};
 
-#[derive(PartialEq, PartialOrd, Copy, Clone, Eq, Ord, Hash)]
+#[derive(PartialEq, PartialOrd, Copy, Clone, Eq, Ord, Hash, Default)]
 pub enum Version {
     Http09,
     Http10,
+    #[default]
     Http11,
 }
 
@@ -20,24 +21,18 @@ impl IntoVersion for Version {
     }
 }
 
-impl<'a> IntoVersion for &'a str {
+impl IntoVersion for &str {
     fn into_version(self) -> Result<Version> {
         self.parse()
     }
 }
 
-impl<'a> IntoVersion for String {
+impl IntoVersion for String {
     fn into_version(self) -> Result<Version> {
         self.parse()
     }
 }
 
-impl Default for Version {
-    fn default() -> Self {
-        Version::Http11
-    }
-}
-
 impl FromStr for Version {
     type Err = Error;
 
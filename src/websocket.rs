@@ -0,0 +1,649 @@
+use std::io::{Read, Write};
+use std::str;
+
+use base64::encode;
+
+use crate::error::{Error, Result};
+use crate::hash::sha1;
+use crate::headers::Headers;
+use crate::http::HttpStream;
+use crate::limits::Limits;
+use crate::random::random_bytes;
+use crate::rate_limit::RateLimiter;
+use crate::stream::Stream;
+use crate::transport::Transport;
+use crate::uri::{IntoUri, Uri};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(code: u8) -> Result<OpCode> {
+        match code {
+            0x0 => Ok(OpCode::Continuation),
+            0x1 => Ok(OpCode::Text),
+            0x2 => Ok(OpCode::Binary),
+            0x8 => Ok(OpCode::Close),
+            0x9 => Ok(OpCode::Ping),
+            0xA => Ok(OpCode::Pong),
+            _ => Err(Error::WebSocketProtocol("unknown opcode")),
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    pub fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: OpCode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(fin: bool, opcode: OpCode, payload: Vec<u8>) -> Frame {
+        Frame {
+            fin,
+            opcode,
+            payload,
+        }
+    }
+
+    // Frames sent by a client are always masked, RFC 6455, section 5.3.
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W, mask: Option<[u8; 4]>) -> Result<()> {
+        let mut head = Vec::with_capacity(14);
+        head.push(if self.fin { 0x80 } else { 0 } | self.opcode.as_u8());
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        let len = self.payload.len();
+        if len < 126 {
+            head.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            head.push(mask_bit | 126);
+            head.extend(&(len as u16).to_be_bytes());
+        } else {
+            head.push(mask_bit | 127);
+            head.extend(&(len as u64).to_be_bytes());
+        }
+        let mut payload = self.payload.clone();
+        if let Some(mask) = mask {
+            head.extend(&mask);
+            apply_mask(&mut payload, mask);
+        }
+        writer.write_all(&head)?;
+        writer.write_all(&payload)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read + ?Sized>(reader: &mut R, max_size: usize) -> Result<Frame> {
+        Ok(Frame::read(reader, max_size)?.0)
+    }
+
+    // Frames sent by a server are never masked, RFC 6455, section 5.1.
+    pub fn read_from_server<R: Read + ?Sized>(reader: &mut R, max_size: usize) -> Result<Frame> {
+        match Frame::read(reader, max_size)? {
+            (_, true) => Err(Error::WebSocketProtocol("masked frame from server")),
+            (frame, false) => Ok(frame),
+        }
+    }
+
+    // Returns the frame and whether it was masked.
+    fn read<R: Read + ?Sized>(reader: &mut R, max_size: usize) -> Result<(Frame, bool)> {
+        let mut head = [0u8; 2];
+        reader.read_exact(&mut head)?;
+        if head[0] & 0x70 != 0 {
+            return Err(Error::WebSocketProtocol("reserved bits set"));
+        }
+        let fin = head[0] & 0x80 != 0;
+        let opcode = OpCode::from_u8(head[0] & 0x0F)?;
+        let masked = head[1] & 0x80 != 0;
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+        if opcode.is_control() && (!fin || len > 125) {
+            return Err(Error::WebSocketProtocol("invalid control frame"));
+        }
+        if len > max_size as u64 {
+            return Err(Error::FrameTooLarge(max_size));
+        }
+        let mask = if masked {
+            let mut mask = [0u8; 4];
+            reader.read_exact(&mut mask)?;
+            Some(mask)
+        } else {
+            None
+        };
+        let mut payload = vec![0u8; len as usize];
+        reader.read_exact(&mut payload)?;
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+        Ok((Frame::new(fin, opcode, payload), masked))
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<(u16, String)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct WebSocketBuilder {
+    uri: Option<Uri>,
+    proxy: Option<Uri>,
+    headers: Headers,
+    protocols: Vec<String>,
+    max_frame_size: usize,
+    max_message_size: usize,
+    fragment_size: Option<usize>,
+    limits: Limits,
+    rate_limit: Option<RateLimiter>,
+}
+
+impl Default for WebSocketBuilder {
+    fn default() -> Self {
+        WebSocketBuilder::new()
+    }
+}
+
+impl WebSocketBuilder {
+    pub fn new() -> WebSocketBuilder {
+        WebSocketBuilder {
+            uri: None,
+            proxy: None,
+            headers: Headers::new(),
+            protocols: Vec::new(),
+            max_frame_size: 16 << 20,
+            max_message_size: 64 << 20,
+            fragment_size: None,
+            limits: Limits::default(),
+            rate_limit: None,
+        }
+    }
+
+    pub fn uri<T: IntoUri>(mut self, uri: T) -> WebSocketBuilder {
+        self.uri = uri.into_uri().ok();
+        self
+    }
+
+    pub fn proxy<T: IntoUri>(mut self, proxy: T) -> WebSocketBuilder {
+        self.proxy = proxy.into_uri().ok();
+        self
+    }
+
+    pub fn header<T: ToString + ?Sized, U: ToString + ?Sized>(
+        mut self,
+        key: &T,
+        value: &U,
+    ) -> WebSocketBuilder {
+        self.headers.insert(key, value);
+        self
+    }
+
+    pub fn protocol(mut self, protocol: &str) -> WebSocketBuilder {
+        self.protocols.push(protocol.to_string());
+        self
+    }
+
+    // Limits for the handshake response and the proxy's CONNECT response.
+    pub fn limits(mut self, limits: Limits) -> WebSocketBuilder {
+        self.limits = limits;
+        self
+    }
+
+    // Takes a token from the limiter before connecting, as clients do.
+    pub fn rate_limit(mut self, limiter: &RateLimiter) -> WebSocketBuilder {
+        self.rate_limit = Some(limiter.clone());
+        self
+    }
+
+    pub fn max_frame_size(mut self, size: usize) -> WebSocketBuilder {
+        self.max_frame_size = size;
+        self
+    }
+
+    pub fn max_message_size(mut self, size: usize) -> WebSocketBuilder {
+        self.max_message_size = size;
+        self
+    }
+
+    // Splits outgoing text and binary messages into frames of at most `size` bytes.
+    pub fn fragment_size(mut self, size: usize) -> WebSocketBuilder {
+        if size > 0 {
+            self.fragment_size = Some(size);
+        }
+        self
+    }
+
+    // Connects directly or through the proxy. HTTP proxies are asked for a
+    // CONNECT tunnel, since they can't forward the upgraded connection.
+    pub fn connect(self) -> Result<WebSocket> {
+        let uri = self.uri.as_ref().ok_or(Error::EmptyUri)?;
+        if let Some(limiter) = &self.rate_limit {
+            limiter.acquire(uri, self.proxy.as_ref())?;
+        }
+        let transport = match &self.proxy {
+            Some(proxy) if matches!(proxy.scheme(), "http" | "https") => {
+                Transport::Stream(HttpStream::tunnel(proxy, uri, &self.limits)?)
+            }
+            Some(proxy) => Transport::proxy(proxy, uri)?,
+            None => Transport::stream(uri)?,
+        };
+        self.handshake(transport)
+    }
+
+    // Performs the opening handshake over an already connected transport.
+    pub fn handshake(self, mut transport: Transport) -> Result<WebSocket> {
+        let uri = self.uri.ok_or(Error::EmptyUri)?;
+        let key = encode(&random_bytes(16));
+        let mut headers = Headers::new();
+        let host = match uri.port() {
+            Some(port) => format!("{}:{}", uri.host(), port),
+            None => uri.host().to_string(),
+        };
+        headers.insert("Host", &host);
+        headers.insert("Upgrade", "websocket");
+        headers.insert("Connection", "Upgrade");
+        headers.insert("Sec-WebSocket-Key", &key);
+        headers.insert("Sec-WebSocket-Version", "13");
+        if !self.protocols.is_empty() {
+            headers.insert("Sec-WebSocket-Protocol", &self.protocols.join(", "));
+        }
+        headers.merge(&self.headers);
+        headers.validate()?;
+        let mut msg = format!("GET {} HTTP/1.1\r\n", uri.request_target());
+        for (key, value) in headers.iter() {
+            msg.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        msg.push_str("\r\n");
+
+        let stream = transport.stream_mut()?;
+        Stream::send_msg(stream, msg.as_bytes())?;
+        let response = Stream::read_head_with(stream, &self.limits)?;
+        if u16::from(response.status_code()) != 101 {
+            return Err(Error::UnexpectedStatus(response.status_code()));
+        }
+        let response_headers = response.headers();
+        if !response_headers
+            .get("Upgrade")
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("websocket"))
+        {
+            return Err(Error::WebSocketHandshake("missing Upgrade: websocket"));
+        }
+        if !response_headers.get("Connection").is_some_and(|v| {
+            v.split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+        }) {
+            return Err(Error::WebSocketHandshake("missing Connection: Upgrade"));
+        }
+        if response_headers
+            .get("Sec-WebSocket-Accept")
+            .as_deref()
+            .map(str::trim)
+            != Some(accept_key(&key).as_str())
+        {
+            return Err(Error::WebSocketHandshake("invalid Sec-WebSocket-Accept"));
+        }
+        let protocol = response_headers.get("Sec-WebSocket-Protocol");
+        if let Some(protocol) = &protocol {
+            if !self.protocols.iter().any(|p| p == protocol.trim()) {
+                return Err(Error::WebSocketHandshake(
+                    "unexpected Sec-WebSocket-Protocol",
+                ));
+            }
+        }
+
+        Ok(WebSocket {
+            transport,
+            protocol,
+            max_frame_size: self.max_frame_size,
+            max_message_size: self.max_message_size,
+            fragment_size: self.fragment_size,
+            fragments: None,
+            close_sent: false,
+            closed: false,
+        })
+    }
+}
+
+pub fn accept_key(key: &str) -> String {
+    encode(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+#[derive(Debug)]
+pub struct WebSocket {
+    transport: Transport,
+    protocol: Option<String>,
+    max_frame_size: usize,
+    max_message_size: usize,
+    fragment_size: Option<usize>,
+    fragments: Option<(OpCode, Vec<u8>)>,
+    close_sent: bool,
+    closed: bool,
+}
+
+impl WebSocket {
+    pub fn builder() -> WebSocketBuilder {
+        WebSocketBuilder::new()
+    }
+
+    pub fn connect<T: IntoUri>(uri: T) -> Result<WebSocket> {
+        WebSocketBuilder::new().uri(uri).connect()
+    }
+
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn send(&mut self, message: Message) -> Result<()> {
+        if self.close_sent {
+            return Err(Error::WebSocketClosed);
+        }
+        let (opcode, payload) = match message {
+            Message::Text(text) => (OpCode::Text, text.into_bytes()),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => return self.send_frame(Frame::new(true, OpCode::Ping, data)),
+            Message::Pong(data) => return self.send_frame(Frame::new(true, OpCode::Pong, data)),
+            Message::Close(close) => return self.send_close(close),
+        };
+        match self.fragment_size {
+            Some(size) if payload.len() > size => {
+                let chunks: Vec<&[u8]> = payload.chunks(size).collect();
+                let last = chunks.len() - 1;
+                for (i, chunk) in chunks.into_iter().enumerate() {
+                    let opcode = if i == 0 { opcode } else { OpCode::Continuation };
+                    self.send_frame(Frame::new(i == last, opcode, chunk.to_vec()))?;
+                }
+                Ok(())
+            }
+            _ => self.send_frame(Frame::new(true, opcode, payload)),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        self.send(Message::Text(text.to_string()))
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.send(Message::Binary(data.to_vec()))
+    }
+
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        self.send(Message::Close(Some((code, reason.to_string()))))
+    }
+
+    fn send_close(&mut self, close: Option<(u16, String)>) -> Result<()> {
+        let payload = match close {
+            Some((code, reason)) => {
+                let mut payload = code.to_be_bytes().to_vec();
+                payload.extend(reason.as_bytes());
+                payload
+            }
+            None => Vec::new(),
+        };
+        self.send_frame(Frame::new(true, OpCode::Close, payload))?;
+        self.close_sent = true;
+        Ok(())
+    }
+
+    fn send_frame(&mut self, frame: Frame) -> Result<()> {
+        let mut mask = [0u8; 4];
+        mask.copy_from_slice(&random_bytes(4));
+        frame.write_to(self.transport.stream_mut()?, Some(mask))
+    }
+
+    // Reads the next message. Pings are answered and a close is echoed before
+    // they are handed to the caller.
+    pub fn read(&mut self) -> Result<Message> {
+        loop {
+            if self.closed {
+                return Err(Error::WebSocketClosed);
+            }
+            let stream = self.transport.stream_mut()?;
+            let frame = match Frame::read_from_server(stream, self.max_frame_size) {
+                Ok(frame) => frame,
+                // A malformed or masked frame fails the connection.
+                Err(e @ Error::WebSocketProtocol(_)) => {
+                    self.closed = true;
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            };
+            match frame.opcode {
+                OpCode::Ping => {
+                    if !self.close_sent {
+                        self.send_frame(Frame::new(true, OpCode::Pong, frame.payload.clone()))?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OpCode::Pong => return Ok(Message::Pong(frame.payload)),
+                OpCode::Close => {
+                    let close = parse_close(&frame.payload)?;
+                    if !self.close_sent {
+                        let echo = close.as_ref().map(|(code, _)| (*code, String::new()));
+                        self.send_close(echo)?;
+                    }
+                    self.closed = true;
+                    return Ok(Message::Close(close));
+                }
+                OpCode::Text | OpCode::Binary => {
+                    if self.fragments.is_some() {
+                        return Err(Error::WebSocketProtocol("expected continuation frame"));
+                    }
+                    self.check_message_size(frame.payload.len())?;
+                    if frame.fin {
+                        return message(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OpCode::Continuation => {
+                    let len = match &self.fragments {
+                        Some((_, data)) => data.len() + frame.payload.len(),
+                        None => return Err(Error::WebSocketProtocol("unexpected continuation")),
+                    };
+                    self.check_message_size(len)?;
+                    if let Some((_, data)) = &mut self.fragments {
+                        data.extend(frame.payload);
+                    }
+                    if frame.fin {
+                        if let Some((opcode, data)) = self.fragments.take() {
+                            return message(opcode, data);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_message_size(&self, len: usize) -> Result<()> {
+        if len > self.max_message_size {
+            Err(Error::MessageTooLarge(self.max_message_size))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn message(opcode: OpCode, data: Vec<u8>) -> Result<Message> {
+    match opcode {
+        OpCode::Text => Ok(Message::Text(String::from_utf8(data)?)),
+        _ => Ok(Message::Binary(data)),
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>> {
+    match payload.len() {
+        0 => Ok(None),
+        1 => Err(Error::WebSocketProtocol("invalid close frame")),
+        _ => {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            let reason = str::from_utf8(&payload[2..])?.to_string();
+            Ok(Some((code, reason)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn websocket_accept_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn websocket_frame() {
+        let frame = Frame::new(true, OpCode::Text, b"Hello".to_vec());
+        let mut buf = Vec::new();
+        frame
+            .write_to(&mut buf, Some([0x37, 0xfa, 0x21, 0x3d]))
+            .unwrap();
+        assert_eq!(
+            buf,
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+        assert_eq!(Frame::read_from(&mut &buf[..], 125).unwrap(), frame);
+        assert!(matches!(
+            Frame::read_from_server(&mut &buf[..], 125),
+            Err(Error::WebSocketProtocol(_))
+        ));
+
+        let frame = Frame::new(true, OpCode::Binary, vec![0; 300]);
+        let mut buf = Vec::new();
+        frame.write_to(&mut buf, None).unwrap();
+        assert_eq!(&buf[..4], &[0x82, 126, 0x01, 0x2c]);
+        assert!(matches!(
+            Frame::read_from(&mut &buf[..], 200),
+            Err(Error::FrameTooLarge(200))
+        ));
+    }
+
+    #[test]
+    fn websocket_rate_limit() {
+        let uri = "ws://127.0.0.1:1/chat";
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).block(false);
+        limiter.acquire(&uri.into_uri().unwrap(), None).unwrap();
+        let result = WebSocketBuilder::new()
+            .uri(uri)
+            .rate_limit(&limiter)
+            .connect();
+        assert!(matches!(result, Err(Error::RateLimited(_))));
+    }
+
+    #[test]
+    fn websocket_echo() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("ws://{}/chat", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let head = crate::limits::read_head(&mut stream, &Limits::default()).unwrap();
+            let head = String::from_utf8(head).unwrap();
+            let key = head
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(key)
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            let mut text = Vec::new();
+            loop {
+                let frame = Frame::read_from(&mut stream, 1024).unwrap();
+                text.extend(frame.payload);
+                if frame.fin {
+                    break;
+                }
+            }
+            Frame::new(false, OpCode::Text, text[..3].to_vec())
+                .write_to(&mut stream, None)
+                .unwrap();
+            Frame::new(true, OpCode::Ping, b"hi".to_vec())
+                .write_to(&mut stream, None)
+                .unwrap();
+            Frame::new(true, OpCode::Continuation, text[3..].to_vec())
+                .write_to(&mut stream, None)
+                .unwrap();
+            let pong = Frame::read_from(&mut stream, 1024).unwrap();
+            Frame::new(true, OpCode::Close, vec![0x03, 0xe8])
+                .write_to(&mut stream, None)
+                .unwrap();
+            let close = Frame::read_from(&mut stream, 1024).unwrap();
+            (head, pong, close)
+        });
+
+        let mut ws = WebSocket::builder()
+            .uri(uri.as_str())
+            .fragment_size(2)
+            .connect()
+            .unwrap();
+        ws.send_text("hello").unwrap();
+        assert_eq!(ws.read().unwrap(), Message::Ping(b"hi".to_vec()));
+        assert_eq!(ws.read().unwrap(), Message::Text("hello".to_string()));
+        assert_eq!(
+            ws.read().unwrap(),
+            Message::Close(Some((1000, String::new())))
+        );
+        assert!(ws.is_closed());
+
+        let (head, pong, close) = server.join().unwrap();
+        assert!(head.starts_with("GET /chat HTTP/1.1\r\n"));
+        assert!(head.contains("Sec-WebSocket-Version: 13\r\n"));
+        assert_eq!(pong, Frame::new(true, OpCode::Pong, b"hi".to_vec()));
+        assert_eq!(close.payload, vec![0x03, 0xe8]);
+    }
+}